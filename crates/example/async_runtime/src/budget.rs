@@ -0,0 +1,97 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use crate::platform::get_clock;
+use crate::get_executor;
+
+/// 每次 poll 默认可调用 consume_budget 的次数
+pub const DEFAULT_POLL_BUDGET: usize = 32;
+
+/// 协程每次被 poll 时获得的时间片预算，任一项用完即视为预算耗尽
+#[derive(Clone, Copy, Debug)]
+pub struct PollBudget {
+    /// consume_budget 的调用次数，0 表示不限
+    pub ops: usize,
+    /// 本次 poll 可运行的时钟周期数，0 表示不限
+    pub cycles: u64,
+}
+
+impl Default for PollBudget {
+    fn default() -> Self {
+        Self {
+            ops: DEFAULT_POLL_BUDGET,
+            cycles: 0,
+        }
+    }
+}
+
+/// 当前协程本次 poll 剩余的预算
+pub(crate) struct BudgetState {
+    ops_left: usize,
+    start: u64,
+}
+
+impl BudgetState {
+    pub const fn new() -> Self {
+        Self {
+            ops_left: 0,
+            start: 0,
+        }
+    }
+
+    /// 协程开始一次新的 poll
+    #[inline]
+    pub fn reset(&mut self, budget: &PollBudget) {
+        self.ops_left = budget.ops;
+        if budget.cycles != 0 {
+            self.start = get_clock();
+        }
+    }
+
+    /// 消耗一次预算，返回预算是否已耗尽
+    #[inline]
+    pub fn consume(&mut self, budget: &PollBudget) -> bool {
+        if budget.ops != 0 {
+            self.ops_left = self.ops_left.saturating_sub(1);
+            if self.ops_left == 0 {
+                return true;
+            }
+        }
+        budget.cycles != 0 && get_clock() - self.start >= budget.cycles
+    }
+}
+
+pub struct ConsumeBudget {
+    yielded: bool,
+}
+
+impl Future for ConsumeBudget {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        let executor = get_executor();
+        let cid = match executor.current {
+            Some(cid) => cid,
+            None => return Poll::Ready(()),
+        };
+        if !executor.consume_budget() {
+            return Poll::Ready(());
+        }
+        // 预算耗尽：排到同优先级就绪队列的末尾，让其他协程先运行
+        self.yielded = true;
+        executor.requeue(&cid);
+        Poll::Pending
+    }
+}
+
+/// 消耗当前协程的一份预算，预算耗尽时让出 CPU，下次被 poll 时获得新的预算。
+/// 长时间运行的协程应在循环中调用，使同优先级的协程能公平地分享 CPU
+#[inline]
+pub fn consume_budget() -> ConsumeBudget {
+    ConsumeBudget {
+        yielded: false,
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::pin::{pin, Pin};
+use core::task::Poll;
+
+/// select 的结果，表示先完成的是哪一个 future
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+// 以下组合子都在当前协程内轮询子 future，不创建新协程。
+// 任一子 future 的唤醒都会唤醒整个协程，协程每次被唤醒都会重新轮询所有未完成的子 future。
+// 组合子本身不区分消息的归属：按 cid 投递到同一邮箱的子 future（例如两个 recv_item）会互相取走对方的消息。
+
+/// 同时等待两个 future，全部完成后返回两者的结果
+pub async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+    let mut a_output = None;
+    let mut b_output = None;
+    poll_fn(|cx| {
+        if a_output.is_none() {
+            if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                a_output = Some(output);
+            }
+        }
+        if b_output.is_none() {
+            if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                b_output = Some(output);
+            }
+        }
+        if a_output.is_some() && b_output.is_some() {
+            return Poll::Ready((a_output.take().unwrap(), b_output.take().unwrap()));
+        }
+        Poll::Pending
+    }).await
+}
+
+/// 同时等待一组 future，全部完成后按原顺序返回结果
+pub async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    let mut pending = futures.len();
+    poll_fn(|cx| {
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_some() {
+                continue;
+            }
+            if let Poll::Ready(res) = future.as_mut().poll(cx) {
+                *output = Some(res);
+                pending -= 1;
+            }
+        }
+        if pending == 0 {
+            return Poll::Ready(outputs.iter_mut().map(|output| output.take().unwrap()).collect());
+        }
+        Poll::Pending
+    }).await
+}
+
+/// 等待两个 future 中先完成的一个，另一个被丢弃。两者同时就绪时优先返回 a
+pub async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = a.as_mut().poll(cx) {
+            return Poll::Ready(Either::Left(output));
+        }
+        if let Poll::Ready(output) = b.as_mut().poll(cx) {
+            return Poll::Ready(Either::Right(output));
+        }
+        Poll::Pending
+    }).await
+}
+
+/// 等待一组 future 中先完成的一个，返回其序号与结果，其余 future 被丢弃。
+/// 传入空列表时永远不会完成
+pub async fn race<F: Future>(futures: Vec<F>) -> (usize, F::Output) {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    poll_fn(|cx| {
+        for (index, future) in futures.iter_mut().enumerate() {
+            if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                return Poll::Ready((index, output));
+            }
+        }
+        Poll::Pending
+    }).await
+}
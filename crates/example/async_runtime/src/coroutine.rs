@@ -4,90 +4,180 @@ use alloc::task::Wake;
 use core::cell::RefCell;
 use core::future::Future;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+use core::sync::atomic::Ordering::Relaxed;
 use core::task::{Context, Poll, Waker};
-use sel4::get_clock;
-use crate::utils::IndexAllocator;
+use crate::platform::get_clock;
+use crate::executor::{WakeState, MAX_PRIO_NUM};
 
+/// 协程 Id：低 CID_INDEX_BITS 位为槽位下标，高位为该槽位的代数。
+/// 槽位回收后代数加一，过期的 Id 不会再唤醒复用该槽位的新协程。
 #[derive(Default, Eq, PartialEq, Debug, Clone, Copy, Hash, Ord, PartialOrd)]
 pub struct CoroutineId(pub u32);
 
-
-#[thread_local]
-static mut CID_ALLOCATOR: IndexAllocator<4096> = IndexAllocator::new();
+pub const CID_INDEX_BITS: u32 = 16;
+pub const MAX_CID_NUM: usize = 1 << CID_INDEX_BITS;
+const CID_INDEX_MASK: u32 = (1 << CID_INDEX_BITS) - 1;
+pub(crate) const CID_GENERATION_MASK: u32 = u32::MAX >> CID_INDEX_BITS;
 
 impl CoroutineId {
-    /// 生成新的协程 Id
-    pub fn generate() -> CoroutineId {
-        // 任务编号计数器，任务编号自增
-        let cid = unsafe { CID_ALLOCATOR.allocate() };
-        CoroutineId(cid.unwrap() as u32)
-    }
     /// 根据 usize 生成协程 Id
     pub const fn from_val(v: u32) -> Self {
         Self(v)
     }
+    /// 根据槽位下标和代数生成协程 Id
+    pub const fn from_parts(index: usize, generation: u32) -> Self {
+        Self((generation & CID_GENERATION_MASK) << CID_INDEX_BITS | (index as u32 & CID_INDEX_MASK))
+    }
     /// 获取协程 Id 的 usize
     pub fn get_val(&self) -> u32 {
         self.0
     }
-
-    pub fn release(&self) {
-        unsafe { CID_ALLOCATOR.release(self.0 as usize) }
+    /// 槽位下标，用于索引按协程存放的数组
+    #[inline]
+    pub const fn index(&self) -> usize {
+        (self.0 & CID_INDEX_MASK) as usize
+    }
+    /// 槽位代数
+    #[inline]
+    pub const fn generation(&self) -> u32 {
+        self.0 >> CID_INDEX_BITS
     }
 }
 
-struct CoroutineWaker(CoroutineId);
+struct CoroutineWaker {
+    cid: CoroutineId,
+    /// 协程所属执行器的地址
+    executor: usize,
+    /// 协程所属执行器的唤醒状态，在其他线程上唤醒时使用
+    wake_state: &'static WakeState,
+}
 
 impl CoroutineWaker {
     /// 新建协程 waker
-    pub fn new(cid: CoroutineId) -> Waker {
-        Waker::from(Arc::new(Self(cid)))
+    pub fn waker(cid: CoroutineId, executor: usize, wake_state: &'static WakeState) -> Waker {
+        Waker::from(Arc::new(Self { cid, executor, wake_state }))
     }
 }
 
 impl Wake for CoroutineWaker {
-    fn wake(self: Arc<Self>) { }
-    fn wake_by_ref(self: &Arc<Self>) { }
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.executor == crate::get_executor_ptr() {
+            crate::get_executor().wake(&self.cid);
+        } else {
+            // 在其他执行器的线程上被唤醒，只能经由唤醒状态通知所属执行器
+            self.wake_state.delay_wake(&self.cid);
+        }
+    }
+}
+
+/// 协程的运行统计
+#[derive(Default, Debug, Clone, Copy)]
+pub struct CoroutineStats {
+    /// 被 poll 的次数
+    pub poll_num: usize,
+    /// 在 execute 中花费的总周期数
+    pub poll_cycles: u64,
+    /// 被唤醒的次数
+    pub wake_num: usize,
+    /// 最近一次被唤醒的时刻，从未被唤醒时为 0
+    pub last_wake: u64,
 }
 
 pub struct Coroutine{
     /// 协程编号
     pub cid: CoroutineId,
-    // 优先级
-    pub prio: usize,
+    // 优先级（可能因优先级继承而高于 base_prio）
+    pub prio: AtomicUsize,
+    /// 基础优先级
+    pub base_prio: AtomicUsize,
+    /// 各优先级上尚未撤销的优先级继承次数
+    pub boosts: [AtomicUsize; MAX_PRIO_NUM],
+    /// 是否已在就绪队列中，用于去除重复唤醒
+    pub queued: AtomicBool,
     /// future
     pub inner: RefCell<CoroutineInner>,
+    poll_num: AtomicUsize,
+    poll_cycles: AtomicU64,
+    wake_num: AtomicUsize,
+    last_wake: AtomicU64,
 }
 
+/// 只在创建它的执行器上运行的 future，不要求 Send
+pub type LocalFuture = Pin<Box<dyn Future<Output=()> + 'static>>;
+
 pub struct CoroutineInner {
-    pub future: Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>>,
+    pub future: LocalFuture,
     /// waker
     pub waker: Arc<Waker>,
 }
 
 impl Coroutine {
     /// 生成协程
-    pub fn new(future: Pin<Box<dyn Future<Output=()> + Send + Sync>>, prio: usize) -> Arc<Self> {
-        let cid = CoroutineId::generate();
+    /// 协程只在所属执行器的线程上运行，Arc 仅用于共享所有权
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new(cid: CoroutineId, future: LocalFuture, prio: usize, wake_state: &'static WakeState) -> Arc<Self> {
         Arc::new(
             Coroutine {
                 cid,
                 inner: RefCell::new(
                     CoroutineInner {
                         future,
-                        waker: Arc::new(CoroutineWaker::new(cid)),
+                        waker: Arc::new(CoroutineWaker::waker(cid, crate::get_executor_ptr(), wake_state)),
                     }
                 )
-                ,prio
+                ,prio: AtomicUsize::new(prio)
+                ,base_prio: AtomicUsize::new(prio)
+                ,boosts: [const { AtomicUsize::new(0) }; MAX_PRIO_NUM]
+                ,queued: AtomicBool::new(false)
+                ,poll_num: AtomicUsize::new(0)
+                ,poll_cycles: AtomicU64::new(0)
+                ,wake_num: AtomicUsize::new(0)
+                ,last_wake: AtomicU64::new(0)
             }
         )
     }
+    #[inline]
+    pub fn get_prio(&self) -> usize {
+        self.prio.load(Relaxed)
+    }
+
+    /// 基础优先级与各次继承中最高的优先级
+    pub fn effective_prio(&self) -> usize {
+        let base_prio = self.base_prio.load(Relaxed);
+        (0..base_prio).find(|&prio| self.boosts[prio].load(Relaxed) > 0).unwrap_or(base_prio)
+    }
+
+    /// 记录一次唤醒
+    #[inline]
+    pub fn record_wake(&self) {
+        self.wake_num.fetch_add(1, Relaxed);
+        self.last_wake.store(get_clock(), Relaxed);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> CoroutineStats {
+        CoroutineStats {
+            poll_num: self.poll_num.load(Relaxed),
+            poll_cycles: self.poll_cycles.load(Relaxed),
+            wake_num: self.wake_num.load(Relaxed),
+            last_wake: self.last_wake.load(Relaxed),
+        }
+    }
+
     /// 执行
     #[inline]
     pub fn execute(self: Arc<Self>) -> Poll<()> {
         let waker = self.inner.borrow().waker.clone();
-        let mut context = Context::from_waker(&*waker);
+        let mut context = Context::from_waker(&waker);
 
-        self.inner.borrow_mut().future.as_mut().poll(&mut context)
+        let start = get_clock();
+        let res = self.inner.borrow_mut().future.as_mut().poll(&mut context);
+        self.poll_cycles.fetch_add(get_clock() - start, Relaxed);
+        self.poll_num.fetch_add(1, Relaxed);
+        res
     }
 }
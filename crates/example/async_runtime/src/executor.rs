@@ -1,160 +1,600 @@
 use alloc::boxed::Box;
-use alloc::sync::Arc;
 use alloc::vec::Vec;
+use alloc::sync::Arc;
+use core::fmt::Debug;
 use core::future::Future;
 use core::pin::Pin;
-use core::sync::atomic::AtomicU64;
-use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{fence, AtomicBool, AtomicU16, AtomicUsize};
+use spin::Mutex;
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
 use core::task::Poll;
-use crate::coroutine::{Coroutine, CoroutineId};
-use crate::utils::{BitMap, BitMap64, RingBuffer};
+use crate::platform::{get_clock, r#yield, CurrentPlatform, Notification, Platform};
+use crate::coroutine::{Coroutine, CoroutineId, CoroutineStats, LocalFuture, MAX_CID_NUM};
+use crate::idle::IdleStrategy;
+use crate::budget::{BudgetState, PollBudget};
+use crate::supervise::{default_failure_hook, FailureHook};
+use crate::timer::TimerQueue;
+use crate::sched::SchedPolicy;
+use crate::task_slab::TaskSlab;
+use crate::utils::AtomicBitMap;
+use crate::multi_core::{has_shared_work, pop_shared, register_executor, shared_tasks_finished, steal_from_others, SharedFuture, SharedTask};
 
+pub const MAX_PRIO_NUM: usize = 8;
+/// 未指定优先级时协程使用的优先级
+pub const DEFAULT_PRIO: usize = 1;
 
-const ARRAY_REPEAT_VALUE: Option<Arc<Coroutine>> = None;
+/// 协程被取消时调用，用于清理运行时之外与 cid 关联的状态
+static ABORT_HOOK: Mutex<Option<fn(&CoroutineId)>> = Mutex::new(None);
+
+pub fn set_abort_hook(hook: fn(&CoroutineId)) {
+    *ABORT_HOOK.lock() = Some(hook);
+}
+
+/// 协程结束或被取消、从执行器中移除后调用，用于清理邮箱等按 cid 保存的状态
+static REMOVE_HOOK: Mutex<Option<fn(&CoroutineId)>> = Mutex::new(None);
+
+pub fn set_remove_hook(hook: fn(&CoroutineId)) {
+    *REMOVE_HOOK.lock() = Some(hook);
+}
+
+/// 一次优先级继承，被释放时撤销
+#[must_use = "释放后优先级继承立即撤销"]
+pub struct PrioBoost {
+    cid: CoroutineId,
+    prio: usize,
+}
+
+impl Drop for PrioBoost {
+    fn drop(&mut self) {
+        crate::get_executor().restore_prio(&self.cid, self.prio);
+    }
+}
+
+/// 执行器中可被其他线程或中断访问的部分：延迟唤醒与空闲等待状态。
+/// 与执行器分开分配，其他线程只通过 &'static WakeState 访问，不会与执行器线程持有的 &mut Executor 重叠
+pub struct WakeState {
+    /// 延迟唤醒可能发生在中断上下文，因此按 cid 槽位使用预先分配的定长位图
+    delay_wake_cids: AtomicBitMap<MAX_CID_NUM>,
+    /// 各槽位当前协程的代数，由执行器在生成协程时写入。
+    /// 延迟唤醒据此直接丢弃过期的 cid，过期唤醒不会覆盖同一槽位上新协程的唤醒
+    live_gens: Box<[AtomicU16]>,
+    /// 被丢弃的过期延迟唤醒次数
+    stale_delay_wake_num: AtomicUsize,
+    /// 执行器是否正阻塞在 idle notification 上
+    sleeping: AtomicBool,
+    /// IdleStrategy::Notification 使用的 notification
+    idle_ntfn: Mutex<Option<Notification>>,
+}
+
+impl WakeState {
+    fn new() -> &'static Self {
+        Box::leak(Box::new(Self {
+            delay_wake_cids: AtomicBitMap::new(),
+            live_gens: (0..MAX_CID_NUM).map(|_| AtomicU16::new(0)).collect(),
+            stale_delay_wake_num: AtomicUsize::new(0),
+            sleeping: AtomicBool::new(false),
+            idle_ntfn: Mutex::new(None),
+        }))
+    }
+
+    /// 记录对 cid 的唤醒，由执行器下次取协程时处理。可在中断上下文或其他线程中调用
+    #[inline]
+    pub fn delay_wake(&self, cid: &CoroutineId) {
+        if self.live_gens[cid.index()].load(Acquire) != cid.generation() as u16 {
+            self.stale_delay_wake_num.fetch_add(1, Relaxed);
+            return;
+        }
+        self.delay_wake_cids.set(cid.index());
+        self.notify_idle();
+    }
+
+    /// 若执行器正阻塞在 idle notification 上则唤醒它，返回是否唤醒了执行器
+    #[inline]
+    pub fn notify_idle(&self) -> bool {
+        // 与执行器一侧的 fence 配对：要么执行器看到已发布的工作，要么这里看到 sleeping
+        fence(SeqCst);
+        if !self.sleeping.swap(false, SeqCst) {
+            return false;
+        }
+        // 执行器只在设置了 notification 后才会睡眠
+        if let Some(ntfn) = *self.idle_ntfn.lock() {
+            CurrentPlatform::signal(&ntfn);
+        }
+        true
+    }
+}
 
-pub const MAX_TASK_NUM: usize = 2048;
-pub const MAX_PRIO_NUM: usize = 8;
 #[repr(align(4096))]
 pub struct Executor {
-    ready_queue: [RingBuffer<CoroutineId, MAX_TASK_NUM>; MAX_PRIO_NUM],
-    prio_bitmap: BitMap64,
-    coroutine_num: usize,
+    policy: Box<dyn SchedPolicy>,
     pub current: Option<CoroutineId>,
-    tasks: [Option<Arc<Coroutine>>; MAX_TASK_NUM],
-    delay_wake_cids: AtomicU64,
-    tasks_bak: Vec<Arc<Coroutine>>,
+    tasks: TaskSlab,
+    wake_state: &'static WakeState,
+    /// 从位图中取出的待唤醒槽位，复用同一块缓冲区
+    delay_wake_buf: Vec<usize>,
+    stale_wake_num: usize,
+    pub(crate) timers: TimerQueue,
+    abort_current: bool,
+    prio_inherit: bool,
+    /// 参与任务窃取时的执行器编号
+    executor_id: Option<usize>,
+    /// 正在运行的共享协程的优先级
+    running_shared: Option<usize>,
+    idle: IdleStrategy,
+    poll_budget: PollBudget,
+    budget: BudgetState,
+    failure_hook: FailureHook,
+    failure_num: usize,
 }
 
 
 impl Executor {
 
-    pub fn new() -> Self {
+    pub fn new(policy: Box<dyn SchedPolicy>) -> Self {
         Self {
-            coroutine_num: 0,
             current: None,
-            tasks: [ARRAY_REPEAT_VALUE; MAX_TASK_NUM],
-            ready_queue: [RingBuffer::new(); MAX_PRIO_NUM],
-            prio_bitmap: BitMap64::new(),
-            tasks_bak: Vec::new(),
-            delay_wake_cids: AtomicU64::new(0),
+            tasks: TaskSlab::new(),
+            policy,
+            wake_state: WakeState::new(),
+            delay_wake_buf: Vec::new(),
+            stale_wake_num: 0,
+            timers: TimerQueue::new(),
+            abort_current: false,
+            prio_inherit: false,
+            executor_id: None,
+            running_shared: None,
+            idle: IdleStrategy::default(),
+            poll_budget: PollBudget::default(),
+            budget: BudgetState::new(),
+            failure_hook: default_failure_hook,
+            failure_num: 0,
         }
     }
 
     pub fn get_ready_num(&self) -> usize {
-        self.ready_queue[2].size()
+        self.policy.ready_num(2)
     }
 
-    pub fn init(&mut self) {
-        *self = Self::new();
+    pub fn init(&mut self, policy: Box<dyn SchedPolicy>) {
+        // the executor memory is zero-initialized, so it must not be dropped in place
+        unsafe { core::ptr::write(self, Self::new(policy)); }
     }
 
     pub fn spawn(&mut self, future: Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>>, prio: usize) -> CoroutineId {
-        let task = Coroutine::new(future, prio);
-        let cid = task.cid;
-        self.prio_bitmap.set(prio);
-        self.ready_queue[prio].push(&cid).unwrap();
-        self.tasks[cid.0 as usize] = Some(task.clone());
-        self.coroutine_num += 1;
-        self.tasks_bak.push(task.clone());
-        return cid;
+        self.spawn_local(future, prio)
     }
 
+    /// 生成不要求 Send 的协程。协程从不离开所属执行器，因此可以持有裸指针或线程局部数据的引用
+    pub fn spawn_local(&mut self, future: LocalFuture, prio: usize) -> CoroutineId {
+        self.spawn_local_with(|_| future, prio)
+    }
+
+    /// 生成协程，future 由 make 根据新协程的 cid 生成
+    pub fn spawn_local_with(&mut self, make: impl FnOnce(CoroutineId) -> LocalFuture, prio: usize) -> CoroutineId {
+        assert!(prio < MAX_PRIO_NUM, "Priority out of range");
+        let wake_state = self.wake_state;
+        let cid = self.tasks.insert(|cid| Coroutine::new(cid, make(cid), prio, wake_state))
+            .expect("Too many coroutines");
+        self.wake_state.live_gens[cid.index()].store(cid.generation() as u16, Release);
+        self.tasks.get(&cid).unwrap().queued.store(true, Relaxed);
+        self.policy.push(cid, prio);
+        cid
+    }
+
+    /// 本执行器的协程都已结束；参与任务窃取时还要求所有共享协程都已结束
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.coroutine_num == 0
+        self.tasks.is_empty() && (self.executor_id.is_none() || shared_tasks_finished())
+    }
+
+    /// 生成可被其他执行器窃取的协程，它每次被唤醒后都可能在另一个执行器上继续运行。
+    /// 执行器没有参与任务窃取时按普通协程生成
+    pub fn spawn_shared(&mut self, future: SharedFuture, prio: usize) {
+        assert!(prio < MAX_PRIO_NUM, "Priority out of range");
+        match self.executor_id {
+            Some(id) => SharedTask::spawn(id, future, prio),
+            None => {
+                self.spawn(future, prio);
+            }
+        }
+    }
+
+    /// 以 id 号加入多核任务窃取
+    pub fn enable_stealing(&mut self, id: usize) {
+        self.executor_id = Some(id);
+        register_executor(id, self.wake_state);
     }
 
+    /// 可在其他线程或中断中使用的唤醒状态
+    #[inline]
+    pub fn wake_state(&self) -> &'static WakeState {
+        self.wake_state
+    }
+
+    /// 取出本执行器共享队列中优先级不低于本地就绪协程的共享协程，steal 为 true 时还会从其他执行器窃取
+    fn fetch_shared(&mut self, steal: bool) -> Option<Arc<SharedTask>> {
+        let id = self.executor_id?;
+        let policy = &self.policy;
+        pop_shared(id, |prio| !policy.preempt_possible(prio))
+            .or_else(|| if steal { steal_from_others(id) } else { None })
+    }
+
+    fn run_shared(&mut self, task: Arc<SharedTask>) {
+        self.running_shared = Some(task.prio());
+        self.budget.reset(&self.poll_budget);
+        task.run(self.executor_id.unwrap());
+        self.running_shared = None;
+    }
+
+    /// 是否有就绪协程应当抢占当前协程，不在协程中时返回 false
     #[inline]
     pub fn switch_possible(&mut self) -> bool {
         self.actual_wake();
-        let task = self.tasks[self.current.unwrap().0 as usize].clone().unwrap();
-        let prio = self.prio_bitmap.find_first_one();
-        prio < task.prio
+        match self.current_prio().or(self.running_shared) {
+            Some(prio) => self.policy.preempt_possible(prio),
+            None => false,
+        }
     }
 
     fn actual_wake(&mut self) {
-        let mut delay_wake_cids = self.delay_wake_cids.swap(0, Relaxed);
-        let mut index = 0;
-        while delay_wake_cids != 0 {
-            if delay_wake_cids & 1 != 0 {
-                self.wake(&CoroutineId::from_val(index));
-                // sel4::debug_println!("delay wake: {}", index);
-                delay_wake_cids &= !(1 << index);
-            }
-            index += 1;
-            delay_wake_cids >>= 1;
+        let state = self.wake_state;
+        if state.delay_wake_cids.empty() {
+            return;
+        }
+        // 先取出所有槽位再逐个唤醒，wake 需要独占执行器
+        let mut indices = core::mem::take(&mut self.delay_wake_buf);
+        state.delay_wake_cids.drain(|index| indices.push(index));
+        for index in indices.drain(..) {
+            // 登记之后槽位可能已被复用，此时唤醒的是新协程，只是一次多余的 poll
+            let generation = state.live_gens[index].load(Acquire) as u32;
+            self.wake(&CoroutineId::from_parts(index, generation));
+            // sel4::debug_println!("delay wake: {}", index);
         }
+        self.delay_wake_buf = indices;
+    }
+
+    fn expire_timers(&mut self) {
+        if self.timers.is_empty() {
+            return;
+        }
+        let now = get_clock();
+        while let Some(waker) = self.timers.pop_expired(now) {
+            waker.wake();
+        }
+    }
+
+    #[inline]
+    pub fn next_timer_deadline(&self) -> Option<u64> {
+        self.timers.next_deadline()
     }
 
     pub fn fetch(&mut self) -> Option<Arc<Coroutine>> {
-        // sel4::debug_println!("fetch, start: {:#x}, start: {}, end: {}", (&self.ready_queue[0]) as *const RingBuffer<CoroutineId, MAX_TASK_NUM_PER_PRIO> as usize,
-        // self.ready_queue[0].start, self.ready_queue[0].end);
         self.actual_wake();
-        let prio = self.prio_bitmap.find_first_one();
-        
-        if prio == 64 {
-            return None;
-        }
-        if let Some(cid) = self.ready_queue[prio].pop() {
-            if let Some(task) = self.tasks[cid.0 as usize].clone() {
-                self.current = Some(cid);
-                if self.ready_queue[prio].empty() {
-                    self.prio_bitmap.clear(prio);
-                }
+        self.expire_timers();
+        while let Some(cid) = self.policy.pop() {
+            if let Some(task) = self.get_task(&cid) {
+                // clear before polling so that a coroutine can wake itself while running
+                task.queued.store(false, Relaxed);
+                self.current = Some(task.cid);
+                self.budget.reset(&self.poll_budget);
                 return Some(task);
             }
         }
         None
     }
 
-    pub fn wake(&mut self, cid: &CoroutineId) {
-        // todo:  need to fix bugs
-        // assert!(self.tasks.contains_key(cid));
-        let op_task = self.tasks[cid.0 as usize].clone();
-        if op_task.is_some() {
-            let prio = op_task.unwrap().prio;
-            self.prio_bitmap.set(prio);
-            // sel4::debug_println!("wake cid: {:?}, start: {:#x}, prio: {}", cid,(&self.ready_queue[prio]) as *const RingBuffer<CoroutineId, MAX_TASK_NUM_PER_PRIO> as usize, prio);
-            self.ready_queue[prio].push(&cid).unwrap();
-            // sel4::debug_println!("wake cid: {}, prio: {}, max_prio: {}", cid.0, prio, self.prio_bitmap.find_first_one());
-            // for i in 0..MAX_PRIO_NUM {
-            //     sel4::debug_println!("[fetch] prio: {}, start: {}, end: {}", i, self.ready_queue[i].start, self.ready_queue[i].end);
-            // }
+    /// 获取 cid 对应的协程，槽位已被回收或复用时返回 None
+    #[inline]
+    fn get_task(&self, cid: &CoroutineId) -> Option<Arc<Coroutine>> {
+        self.tasks.get(cid).cloned()
+    }
+
+    #[inline]
+    pub fn is_alive(&self, cid: &CoroutineId) -> bool {
+        self.get_task(cid).is_some()
+    }
+
+    #[inline]
+    pub fn get_stale_wake_num(&self) -> usize {
+        self.stale_wake_num + self.wake_state.stale_delay_wake_num.load(Relaxed)
+    }
+
+    /// 协程的运行统计，cid 已过期时返回 None
+    #[inline]
+    pub fn stats(&self, cid: &CoroutineId) -> Option<CoroutineStats> {
+        self.get_task(cid).map(|task| task.stats())
+    }
+
+    /// 打印所有存活协程的 cid、优先级、状态与运行统计
+    pub fn dump(&self) {
+        let ready_num: usize = (0..MAX_PRIO_NUM).map(|prio| self.policy.ready_num(prio)).sum();
+        debug_println!("executor: {} coroutines, {} ready, {} stale wakes, {} failures",
+            self.tasks.len(), ready_num, self.get_stale_wake_num(), self.failure_num);
+        for task in self.tasks.iter() {
+            let state = if self.current == Some(task.cid) {
+                "running"
+            } else if task.queued.load(Relaxed) {
+                "ready"
+            } else {
+                "blocked"
+            };
+            let stats = task.stats();
+            debug_println!("  cid: {:#x}, prio: {}/{}, state: {}, polls: {}, cycles: {}, wakes: {}, last wake: {}",
+                task.cid.0, task.get_prio(), task.base_prio.load(Relaxed), state,
+                stats.poll_num, stats.poll_cycles, stats.wake_num, stats.last_wake);
+        }
+    }
+
+    /// 唤醒协程，cid 已过期时丢弃本次唤醒并返回 false
+    pub fn wake(&mut self, cid: &CoroutineId) -> bool {
+        if let Some(task) = self.get_task(cid) {
+            task.record_wake();
+        }
+        self.requeue(cid)
+    }
+
+    /// 把协程放回就绪队列，不计入唤醒统计。用于协程主动让出，例如预算耗尽
+    pub(crate) fn requeue(&mut self, cid: &CoroutineId) -> bool {
+        let op_task = self.get_task(cid);
+        if let Some(task) = op_task {
+            if task.queued.swap(true, Relaxed) {
+                // already in the ready queue, a duplicate wake must not poll it twice
+                return true;
+            }
+            let prio = task.get_prio();
+            // sel4::debug_println!("wake cid: {:?}, prio: {}", cid, prio);
+            self.policy.push(*cid, prio);
+            return true;
         }
-        
+        self.stale_wake_num += 1;
+        false
     }
 
     #[inline]
-    pub fn delay_wake(&mut self, cid: &CoroutineId) {
-        assert!(cid.0 < 64);
-        let current= self.delay_wake_cids.load(Relaxed);
-        self.delay_wake_cids.store(current | 1 << cid.0, Relaxed);
+    pub fn delay_wake(&self, cid: &CoroutineId) {
+        self.wake_state.delay_wake(cid);
     }
 
 
     #[inline]
     pub fn remove_task(&mut self, cid: CoroutineId) {
-        self.tasks[cid.0 as usize] = None;
-        self.coroutine_num -= 1;
-        cid.release();
+        // 返回的协程在 slab 操作结束后才释放，其 future 的析构可以安全地访问执行器
+        let task = self.tasks.remove(&cid);
+        drop(task);
+        // 协程已不存活，之后不会再有消息投递给它
+        let hook = *REMOVE_HOOK.lock();
+        if let Some(hook) = hook {
+            hook(&cid);
+        }
+    }
+
+    #[inline]
+    fn dequeue(&mut self, cid: &CoroutineId, prio: usize) -> bool {
+        self.policy.remove(cid, prio)
+    }
+
+    /// 修改协程当前生效的优先级，已在就绪队列中的协程会被移动到新优先级的队列
+    fn change_prio(&mut self, task: &Arc<Coroutine>, prio: usize) {
+        let old_prio = task.prio.swap(prio, Relaxed);
+        if old_prio == prio {
+            return;
+        }
+        if task.queued.load(Relaxed) && self.dequeue(&task.cid, old_prio) {
+            self.policy.push(task.cid, prio);
+        }
+    }
+
+    /// 设置协程的基础优先级，优先级越界或协程不存在时返回 false。
+    /// 仍在生效的优先级继承会保留，生效的优先级取两者中较高的一个
+    pub fn set_prio(&mut self, cid: &CoroutineId, prio: usize) -> bool {
+        if prio >= MAX_PRIO_NUM {
+            return false;
+        }
+        if let Some(task) = self.get_task(cid) {
+            task.base_prio.store(prio, Relaxed);
+            self.change_prio(&task, task.effective_prio());
+            return true;
+        }
+        false
+    }
+
+    /// 协程当前生效的优先级
+    pub fn prio(&self, cid: &CoroutineId) -> Option<usize> {
+        self.get_task(cid).map(|task| task.get_prio())
+    }
+
+    /// 当前协程的优先级，不在协程中时返回 None
+    pub fn current_prio(&self) -> Option<usize> {
+        self.current.and_then(|cid| self.prio(&cid))
+    }
+
+    #[inline]
+    pub fn set_prio_inherit(&mut self, enable: bool) {
+        self.prio_inherit = enable;
+    }
+
+    /// 优先级继承：等待者以 prio 等待 cid 时登记一次提升，返回的 PrioBoost 被释放时撤销。
+    /// 同一协程可以同时被多个等待者提升，生效的是其中最高的优先级。
+    /// 未开启继承、优先级越界或协程不存在时返回 None
+    pub fn inherit_prio(&mut self, cid: &CoroutineId, prio: usize) -> Option<PrioBoost> {
+        if !self.prio_inherit || prio >= MAX_PRIO_NUM {
+            return None;
+        }
+        let task = self.get_task(cid)?;
+        task.boosts[prio].fetch_add(1, Relaxed);
+        self.change_prio(&task, task.effective_prio());
+        Some(PrioBoost { cid: *cid, prio })
+    }
+
+    /// 撤销一次优先级继承
+    fn restore_prio(&mut self, cid: &CoroutineId, prio: usize) {
+        if let Some(task) = self.get_task(cid) {
+            let _ = task.boosts[prio].fetch_update(Relaxed, Relaxed, |num| num.checked_sub(1));
+            self.change_prio(&task, task.effective_prio());
+        }
+    }
+
+    /// 取消协程：释放其 future 与 cid，并从就绪队列中移除。
+    /// 正在运行的协程会在本次 poll 返回后被取消。
+    pub fn abort(&mut self, cid: &CoroutineId) -> bool {
+        if !self.is_alive(cid) {
+            return false;
+        }
+        if self.current == Some(*cid) {
+            self.abort_current = true;
+            return true;
+        }
+        self.destroy_task(*cid);
+        true
+    }
+
+    fn destroy_task(&mut self, cid: CoroutineId) {
+        let task = self.get_task(&cid).unwrap();
+        // 不从就绪队列中删除（线性查找），留在队列中的 cid 在 fetch 时因已过期被跳过
+        task.queued.store(false, Relaxed);
+        // drop the future before the abort hook runs, the scheduler may still hold the coroutine
+        let future = core::mem::replace(&mut task.inner.borrow_mut().future, Box::pin(async {}));
+        drop(future);
+        let hook = *ABORT_HOOK.lock();
+        if let Some(hook) = hook {
+            hook(&cid);
+        }
+        self.remove_task(cid);
+    }
+
+    /// 设置本执行器上协程返回 Err 时的处理函数
+    #[inline]
+    pub fn set_failure_hook(&mut self, hook: FailureHook) {
+        self.failure_hook = hook;
+    }
+
+    pub fn report_failure(&mut self, cid: &CoroutineId, err: &dyn Debug) {
+        self.failure_num += 1;
+        (self.failure_hook)(cid, err);
+    }
+
+    #[inline]
+    pub fn get_failure_num(&self) -> usize {
+        self.failure_num
+    }
+
+    #[inline]
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.poll_budget = budget;
+    }
+
+    /// 消耗当前协程的一份预算，返回本次 poll 的预算是否已耗尽
+    #[inline]
+    pub fn consume_budget(&mut self) -> bool {
+        self.budget.consume(&self.poll_budget)
+    }
+
+    #[inline]
+    pub fn set_idle(&mut self, idle: IdleStrategy) {
+        self.idle = idle;
+        *self.wake_state.idle_ntfn.lock() = match idle {
+            IdleStrategy::Notification(ntfn) => Some(ntfn),
+            _ => None,
+        };
+    }
+
+    /// 若执行器正阻塞在 idle notification 上则唤醒它
+    #[inline]
+    pub fn notify_idle(&self) {
+        self.wake_state.notify_idle();
+    }
+
+    /// 是否有尚未进入就绪队列的工作：延迟唤醒、可运行或窃取的共享协程或到期的定时器
+    fn has_pending_work(&self) -> bool {
+        !self.wake_state.delay_wake_cids.empty()
+            || (self.executor_id.is_some() && has_shared_work())
+            || matches!(self.timers.next_deadline(), Some(deadline) if deadline <= get_clock())
+    }
+
+    /// 没有就绪协程时等待新的工作
+    #[inline]
+    pub fn idle_wait(&mut self) {
+        self.wait_for_work(false);
+    }
+
+    /// 等待新的工作，until_empty 为 true 时执行器变空（例如最后一个共享协程在其他执行器上结束）也会返回
+    fn wait_for_work(&mut self, until_empty: bool) {
+        match self.idle {
+            IdleStrategy::Yield => r#yield(),
+            IdleStrategy::Notification(ntfn) => {
+                // 先声明即将睡眠再检查工作，之后的唤醒者一定能看到 sleeping 并 signal；
+                // notification 会记住睡眠前到达的 signal，因此不会丢失唤醒
+                let sleeping = &self.wake_state.sleeping;
+                sleeping.store(true, SeqCst);
+                fence(SeqCst);
+                if !self.has_pending_work() && (!until_empty || !self.is_empty()) {
+                    match self.timers.next_deadline() {
+                        None => CurrentPlatform::wait(&ntfn),
+                        // wait_until 可能提前返回；唤醒者会先清除 sleeping 再 signal
+                        Some(deadline) => while sleeping.load(SeqCst) && get_clock() < deadline {
+                            CurrentPlatform::wait_until(&ntfn, deadline);
+                        },
+                    }
+                }
+                sleeping.store(false, SeqCst);
+            }
+            IdleStrategy::Hook(hook) => hook(),
+        }
     }
 
     pub fn run_until_complete(&mut self) {
         while !self.is_empty() {
             self.run_until_blocked();
+            if !self.is_empty() {
+                self.wait_for_work(true);
+            }
         }
     }
 
+    pub fn run_forever(&mut self) -> ! {
+        loop {
+            self.run_until_blocked();
+            self.idle_wait();
+        }
+    }
+
+    /// 运行本地协程和共享协程，直到没有可运行的协程
     pub fn run_until_blocked(&mut self) {
-        while let Some(task) = self.fetch() {
-            let cid = task.cid;
-            // sel4::debug_println!("run_until_blocked loop");
-            match task.execute() {
-                Poll::Ready(_) => {
-                    self.remove_task(cid);
-                }
-                Poll::Pending => {
-                    // self.pending(cid);
+        loop {
+            self.actual_wake();
+            self.expire_timers();
+            // 优先级不低于本地就绪协程的共享协程先运行
+            if let Some(task) = self.fetch_shared(false) {
+                self.run_shared(task);
+                continue;
+            }
+            match self.fetch() {
+                Some(task) => self.run_local(task),
+                None => match self.fetch_shared(true) {
+                    Some(task) => self.run_shared(task),
+                    None => break,
+                },
+            }
+        }
+    }
+
+    fn run_local(&mut self, task: Arc<Coroutine>) {
+        let cid = task.cid;
+        // sel4::debug_println!("run_until_blocked loop");
+        let res = task.execute();
+        // 协程之外（例如中断处理或两次 poll 之间）不存在当前协程
+        self.current = None;
+        self.policy.leave();
+        match res {
+            Poll::Ready(_) => {
+                self.abort_current = false;
+                self.remove_task(cid);
+            }
+            Poll::Pending => {
+                // self.pending(cid);
+                if self.abort_current {
+                    self.abort_current = false;
+                    self.destroy_task(cid);
                 }
             }
         }
@@ -0,0 +1,17 @@
+use crate::platform::Notification;
+
+/// 执行器没有就绪协程时的等待方式
+#[derive(Clone, Copy, Default)]
+pub enum IdleStrategy {
+    /// 让出 CPU 后立即重试（默认）
+    #[default]
+    Yield,
+    /// 阻塞在专用于空闲等待的 notification 上，只有 WakeState::notify_idle 会 signal 它：
+    /// uintr handler 与其他线程的唤醒都经 delay_wake 调用 notify_idle。
+    /// 不要使用绑定到 TCB 或注册为 uintr 接收端的 notification，阻塞在它上面时无法确定 uipi 能否唤醒线程。
+    /// 有未到期的定时器时最多等到最早的定时器到期；seL4 上的等待不能设置超时，
+    /// 此时改为轮询 notification 并让出 CPU，直到被唤醒或定时器到期
+    Notification(Notification),
+    /// 调用自定义的等待函数
+    Hook(fn()),
+}
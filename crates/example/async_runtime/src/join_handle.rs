@@ -0,0 +1,175 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use crate::coroutine::{CoroutineId, LocalFuture};
+use crate::executor::PrioBoost;
+use crate::multi_core::SharedFuture;
+use crate::get_executor;
+
+struct JoinInner<T> {
+    output: Option<T>,
+    finished: bool,
+    cancelled: bool,
+    waiter: Option<Waker>,
+}
+
+pub(crate) struct JoinState<T> {
+    inner: Mutex<JoinInner<T>>,
+}
+
+impl<T> JoinState<T> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(JoinInner {
+                output: None,
+                finished: false,
+                cancelled: false,
+                waiter: None,
+            })
+        })
+    }
+
+    pub fn finish(&self, output: T) {
+        let mut inner = self.inner.lock();
+        inner.output = Some(output);
+        inner.finished = true;
+        if let Some(waiter) = inner.waiter.take() {
+            drop(inner);
+            waiter.wake();
+        }
+    }
+
+    pub fn cancel(&self) {
+        let mut inner = self.inner.lock();
+        if inner.finished {
+            return;
+        }
+        inner.finished = true;
+        inner.cancelled = true;
+        if let Some(waiter) = inner.waiter.take() {
+            drop(inner);
+            waiter.wake();
+        }
+    }
+}
+
+/// 协程的 future 被提前释放（取消）时通知句柄
+struct CancelGuard<T>(Arc<JoinState<T>>);
+
+impl<T> Drop for CancelGuard<T> {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// 协程句柄，await 后得到协程的返回值，协程被取消时得到 Err(())
+pub struct JoinHandle<T> {
+    cid: CoroutineId,
+    state: Arc<JoinState<T>>,
+    /// 等待者对协程的优先级继承，句柄完成或被释放时撤销
+    boost: Option<PrioBoost>,
+}
+
+impl<T: Send + 'static> JoinHandle<T> {
+    /// 包装 future，协程结束时把返回值写入句柄
+    pub(crate) fn wrap(future: Pin<Box<dyn Future<Output=T> + 'static + Send + Sync>>)
+        -> (SharedFuture, Arc<JoinState<T>>) {
+        let state = JoinState::new();
+        let child_state = state.clone();
+        // 守卫在协程创建时就存在，尚未被 poll 就被取消的协程同样会通知句柄
+        let guard = CancelGuard(child_state);
+        let wrapped = Box::pin(async move {
+            let output = future.await;
+            guard.0.finish(output);
+        });
+        (wrapped, state)
+    }
+
+}
+
+impl<T: 'static> JoinHandle<T> {
+    /// 包装不要求 Send 的 future
+    pub(crate) fn wrap_local(future: Pin<Box<dyn Future<Output=T> + 'static>>) -> (LocalFuture, Arc<JoinState<T>>) {
+        let state = JoinState::new();
+        let child_state = state.clone();
+        let guard = CancelGuard(child_state);
+        let wrapped = Box::pin(async move {
+            let output = future.await;
+            guard.0.finish(output);
+        });
+        (wrapped, state)
+    }
+}
+
+impl<T> JoinHandle<T> {
+    pub(crate) fn new(cid: CoroutineId, state: Arc<JoinState<T>>) -> Self {
+        Self {
+            cid,
+            state,
+            boost: None,
+        }
+    }
+
+    #[inline]
+    pub fn cid(&self) -> CoroutineId {
+        self.cid
+    }
+
+    /// 取消协程
+    #[inline]
+    pub fn abort(&self) {
+        crate::coroutine_abort(&self.cid);
+    }
+
+    /// 协程是否已经执行完毕
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.state.inner.lock().finished
+    }
+
+    /// 不等待地取出协程的返回值，协程尚未结束时返回 None
+    pub fn try_take(&self) -> Option<Result<T, ()>> {
+        let mut inner = self.state.inner.lock();
+        if inner.cancelled {
+            return Some(Err(()));
+        }
+        if inner.finished {
+            return Some(Ok(inner.output.take().expect("JoinHandle polled after completion")));
+        }
+        None
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, ()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut inner = this.state.inner.lock();
+        if inner.cancelled {
+            drop(inner);
+            this.boost = None;
+            return Poll::Ready(Err(()));
+        }
+        if inner.finished {
+            let output = inner.output.take().expect("JoinHandle polled after completion");
+            drop(inner);
+            this.boost = None;
+            return Poll::Ready(Ok(output));
+        }
+        inner.waiter = Some(cx.waker().clone());
+        drop(inner);
+        if this.boost.is_none() {
+            let executor = get_executor();
+            if let (Some(current), Some(prio)) = (executor.current, executor.current_prio()) {
+                if current != this.cid {
+                    this.boost = executor.inherit_prio(&this.cid, prio);
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
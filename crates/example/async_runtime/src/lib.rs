@@ -1,54 +1,86 @@
-#![no_std]
-#![feature(thread_local)]
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "sel4", feature(thread_local))]
+#![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
 #![feature(core_intrinsics)]
+#![feature(inline_const)]
 extern crate alloc;
 
+#[macro_use]
+pub mod platform;
 mod executor;
 mod coroutine;
 mod new_buffer;
+mod payload;
+mod pending_call;
 mod message_info;
+mod timer;
+mod join_handle;
+mod sched;
+mod multi_core;
+mod idle;
+mod mailbox;
+mod task_group;
+mod combinator;
+mod budget;
+mod task_slab;
+mod supervise;
+pub mod sync;
 pub mod utils;
+pub mod queue;
 
 use alloc::alloc::alloc_zeroed;
 use alloc::boxed::Box;
 use core::alloc::Layout;
+use core::fmt::Debug;
 use core::future::Future;
 use core::mem::size_of;
 use core::pin::Pin;
+use supervise::{wrap_fallible, wrap_supervised};
+use platform::{CurrentPlatform, Platform};
 pub use executor::*;
 pub use new_buffer::*;
+pub use payload::*;
+pub use pending_call::{PendingCalls, ReplyError, ReplyFuture};
 pub use coroutine::*;
 pub use message_info::*;
-
-#[thread_local]
-static mut EXECUTOR: usize = 0;
+pub use timer::*;
+pub use join_handle::JoinHandle;
+pub use sched::*;
+pub use multi_core::{MAX_EXECUTOR_NUM, SharedFuture, SharedTask};
+pub use idle::IdleStrategy;
+pub use mailbox::*;
+pub use task_group::TaskGroup;
+pub use combinator::*;
+pub use budget::{consume_budget, PollBudget, DEFAULT_POLL_BUDGET};
+pub use supervise::{default_failure_hook, FailureHook, FallibleFuture, RestartPolicy, ALWAYS_RESTART_BACKOFF};
 
 #[inline]
 pub fn get_executor() -> &'static mut Executor {
     unsafe {
-        &mut *(EXECUTOR as *mut Executor)
+        &mut *(CurrentPlatform::executor_ptr() as *mut Executor)
     }
 }
 
 #[inline]
 pub fn runtime_init() {
+    runtime_init_with_policy(Box::new(FixedPrioPolicy::new()));
+}
+
+/// 初始化当前线程的执行器，并指定其调度策略
+pub fn runtime_init_with_policy(policy: Box<dyn SchedPolicy>) {
     let rt_layout = Layout::from_size_align(size_of::<Executor>(), 4096).expect("Failed to create layout for page aligned memory allocation");
-    unsafe {
-        EXECUTOR = {
-            let ptr = alloc_zeroed(rt_layout);
-            if ptr.is_null() {
-                panic!("Failed to allocate page aligned memory");
-            }
-            ptr as usize
-        }
+    let ptr = unsafe { alloc_zeroed(rt_layout) };
+    if ptr.is_null() {
+        panic!("Failed to allocate page aligned memory");
     }
-    get_executor().init();
+    CurrentPlatform::set_executor_ptr(ptr as usize);
+    get_executor().init(policy);
 }
 
 #[inline]
 pub fn coroutine_spawn(future: Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>>) -> CoroutineId {
-    get_executor().spawn(future, 1)
+    get_executor().spawn(future, DEFAULT_PRIO)
 }
 
 #[inline]
@@ -56,6 +88,115 @@ pub fn coroutine_spawn_with_prio(future: Pin<Box<dyn Future<Output=()> + 'static
     get_executor().spawn(future, prio)
 }
 
+#[inline]
+pub fn coroutine_spawn_local(future: LocalFuture) -> CoroutineId {
+    get_executor().spawn_local(future, DEFAULT_PRIO)
+}
+
+#[inline]
+pub fn coroutine_spawn_local_with_prio(future: LocalFuture, prio: usize) -> CoroutineId {
+    get_executor().spawn_local(future, prio)
+}
+
+#[inline]
+pub fn coroutine_spawn_joinable<T: Send + 'static>(future: Pin<Box<dyn Future<Output=T> + 'static + Send + Sync>>) -> JoinHandle<T> {
+    coroutine_spawn_joinable_with_prio(future, DEFAULT_PRIO)
+}
+
+#[inline]
+pub fn coroutine_spawn_joinable_with_prio<T: Send + 'static>(future: Pin<Box<dyn Future<Output=T> + 'static + Send + Sync>>, prio: usize) -> JoinHandle<T> {
+    let (wrapped, state) = JoinHandle::wrap(future);
+    let cid = get_executor().spawn(wrapped, prio);
+    JoinHandle::new(cid, state)
+}
+
+#[inline]
+pub fn coroutine_spawn_local_joinable<T: 'static>(future: Pin<Box<dyn Future<Output=T> + 'static>>) -> JoinHandle<T> {
+    coroutine_spawn_local_joinable_with_prio(future, DEFAULT_PRIO)
+}
+
+#[inline]
+pub fn coroutine_spawn_local_joinable_with_prio<T: 'static>(future: Pin<Box<dyn Future<Output=T> + 'static>>, prio: usize) -> JoinHandle<T> {
+    let (wrapped, state) = JoinHandle::wrap_local(future);
+    let cid = get_executor().spawn_local(wrapped, prio);
+    JoinHandle::new(cid, state)
+}
+
+/// 生成返回 Result 的协程，返回 Err 时调用执行器的失败处理函数
+#[inline]
+pub fn coroutine_spawn_fallible<E: Debug + 'static>(future: FallibleFuture<E>, prio: usize) -> CoroutineId {
+    get_executor().spawn_local_with(|cid| wrap_fallible(future, cid), prio)
+}
+
+/// 生成受监督的协程，失败后按 policy 用 factory 生成的新 future 重启
+#[inline]
+pub fn coroutine_spawn_supervised<E, F>(factory: F, policy: RestartPolicy, prio: usize) -> CoroutineId
+    where E: Debug + 'static, F: FnMut() -> FallibleFuture<E> + 'static + Send + Sync {
+    get_executor().spawn_local_with(|cid| wrap_supervised(factory, policy, cid), prio)
+}
+
+#[inline]
+pub fn coroutine_set_failure_hook(hook: FailureHook) {
+    get_executor().set_failure_hook(hook);
+}
+
+#[inline]
+pub fn coroutine_abort(cid: &CoroutineId) -> bool {
+    get_executor().abort(cid)
+}
+
+#[inline]
+pub fn coroutine_set_abort_hook(hook: fn(&CoroutineId)) {
+    set_abort_hook(hook);
+}
+
+#[inline]
+pub fn coroutine_set_remove_hook(hook: fn(&CoroutineId)) {
+    set_remove_hook(hook);
+}
+
+#[inline]
+pub fn coroutine_set_prio(cid: &CoroutineId, prio: usize) -> bool {
+    get_executor().set_prio(cid, prio)
+}
+
+/// 协程当前生效的优先级（含优先级继承），协程不存在时返回 None
+#[inline]
+pub fn coroutine_prio(cid: &CoroutineId) -> Option<usize> {
+    get_executor().prio(cid)
+}
+
+/// 当前协程的优先级，不在协程中时返回 None
+#[inline]
+pub fn coroutine_current_prio() -> Option<usize> {
+    get_executor().current_prio()
+}
+
+/// 开启或关闭优先级继承（默认关闭）
+#[inline]
+pub fn coroutine_set_prio_inherit(enable: bool) {
+    get_executor().set_prio_inherit(enable)
+}
+
+/// 把协程 cid 的优先级临时提升到 prio，返回值被释放时撤销；未开启优先级继承时返回 None
+#[inline]
+pub fn coroutine_inherit_prio(cid: &CoroutineId, prio: usize) -> Option<PrioBoost> {
+    get_executor().inherit_prio(cid, prio)
+}
+
+/// 生成可被其他核上执行器窃取的协程，见 SharedTask
+#[inline]
+pub fn coroutine_spawn_shared(future: SharedFuture, prio: usize) {
+    get_executor().spawn_shared(future, prio)
+}
+
+/// 多核模式：当前线程的执行器以 id 号参与任务窃取。
+/// 其他核上的唤醒和新的共享协程会 signal 阻塞在 IdleStrategy::Notification 上的执行器
+#[inline]
+pub fn runtime_enable_multi_core(id: usize) {
+    get_executor().enable_stealing(id);
+}
+
 #[inline]
 pub fn coroutine_possible_switch() -> bool {
     get_executor().switch_possible()
@@ -67,12 +208,34 @@ pub fn coroutine_delay_wake(cid: &CoroutineId) {
     get_executor().delay_wake(cid);
 }
 
+/// 唤醒协程，cid 已过期时返回 false
+#[inline]
+pub fn coroutine_wake(cid: &CoroutineId) -> bool {
+    get_executor().wake(cid)
+}
+
+#[inline]
+pub fn coroutine_is_alive(cid: &CoroutineId) -> bool {
+    get_executor().is_alive(cid)
+}
+
 #[inline]
-pub fn coroutine_wake(cid: &CoroutineId) {
-    get_executor().wake(cid);
+pub fn coroutine_stale_wake_num() -> usize {
+    get_executor().get_stale_wake_num()
 }
 
 
+#[inline]
+pub fn coroutine_stats(cid: &CoroutineId) -> Option<CoroutineStats> {
+    get_executor().stats(cid)
+}
+
+/// 打印当前执行器中所有存活协程的状态与运行统计
+#[inline]
+pub fn coroutine_dump() {
+    get_executor().dump()
+}
+
 #[inline]
 pub fn coroutine_get_current() -> CoroutineId {
     get_executor().current.unwrap()
@@ -80,9 +243,7 @@ pub fn coroutine_get_current() -> CoroutineId {
 
 #[inline]
 pub fn get_executor_ptr() -> usize {
-    unsafe {
-        EXECUTOR
-    }
+    CurrentPlatform::executor_ptr()
 }
 
 #[inline]
@@ -104,3 +265,33 @@ pub fn coroutine_is_empty() -> bool {
 pub fn coroutine_run_until_complete() {
     get_executor().run_until_complete()
 }
+
+/// 设置协程每次被 poll 时的时间片预算，见 consume_budget
+#[inline]
+pub fn runtime_set_poll_budget(budget: PollBudget) {
+    get_executor().set_poll_budget(budget);
+}
+
+/// 设置执行器没有就绪协程时的等待方式
+#[inline]
+pub fn runtime_set_idle(idle: IdleStrategy) {
+    get_executor().set_idle(idle)
+}
+
+/// 持续运行协程，没有就绪协程时按 IdleStrategy 等待
+#[inline]
+pub fn run_forever() -> ! {
+    get_executor().run_forever()
+}
+
+/// 以协程方式运行 future 直到其结束并返回结果，期间其他协程也会被调度
+pub fn block_on<T: Send + 'static>(future: Pin<Box<dyn Future<Output=T> + 'static + Send + Sync>>) -> T {
+    let handle = coroutine_spawn_joinable(future);
+    loop {
+        get_executor().run_until_blocked();
+        if let Some(output) = handle.try_take() {
+            return output.expect("block_on future was aborted");
+        }
+        get_executor().idle_wait();
+    }
+}
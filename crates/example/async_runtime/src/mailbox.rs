@@ -0,0 +1,82 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use core::future::poll_fn;
+use core::task::Poll;
+use spin::Mutex;
+use crate::coroutine::CoroutineId;
+use crate::{coroutine_get_current, coroutine_is_alive, coroutine_wake};
+
+/// 每个协程邮箱默认最多缓存的消息数
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 16;
+
+/// 按协程划分的有界邮箱：向协程投递消息并唤醒它，协程按投递顺序取出。
+/// 以 cid 槽位为键，槽位被新协程复用时旧消息被丢弃。
+pub struct Mailbox<T> {
+    boxes: Mutex<BTreeMap<usize, (CoroutineId, VecDeque<T>)>>,
+    capacity: usize,
+}
+
+impl<T> Mailbox<T> {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            boxes: Mutex::new(BTreeMap::new()),
+            capacity,
+        }
+    }
+
+    /// 投递消息并唤醒协程；协程已结束或邮箱已满时原样返回消息
+    pub fn send(&self, cid: &CoroutineId, value: T) -> Result<(), T> {
+        if !coroutine_is_alive(cid) {
+            return Err(value);
+        }
+        let mut boxes = self.boxes.lock();
+        let entry = boxes.entry(cid.index()).or_insert_with(|| (*cid, VecDeque::new()));
+        if entry.0 != *cid {
+            // 槽位已被新协程复用
+            *entry = (*cid, VecDeque::new());
+        }
+        if entry.1.len() >= self.capacity {
+            return Err(value);
+        }
+        entry.1.push_back(value);
+        drop(boxes);
+        coroutine_wake(cid);
+        Ok(())
+    }
+
+    /// 不等待地取出协程的下一条消息
+    pub fn try_recv(&self, cid: &CoroutineId) -> Option<T> {
+        let mut boxes = self.boxes.lock();
+        match boxes.get_mut(&cid.index()) {
+            Some((owner, queue)) if owner == cid => queue.pop_front(),
+            _ => None,
+        }
+    }
+
+    /// 当前协程等待下一条消息
+    pub async fn recv(&self) -> T {
+        let cid = coroutine_get_current();
+        // 消息投递时会按 cid 唤醒协程，因此无需登记 waker
+        poll_fn(|_cx| {
+            match self.try_recv(&cid) {
+                Some(value) => Poll::Ready(value),
+                None => Poll::Pending,
+            }
+        }).await
+    }
+
+    /// 当前协程邮箱中待取出的消息数
+    pub fn len(&self, cid: &CoroutineId) -> usize {
+        match self.boxes.lock().get(&cid.index()) {
+            Some((owner, queue)) if owner == cid => queue.len(),
+            _ => 0,
+        }
+    }
+
+    /// 丢弃协程的全部消息
+    pub fn clear(&self, cid: &CoroutineId) {
+        let mut boxes = self.boxes.lock();
+        if matches!(boxes.get(&cid.index()), Some((owner, _)) if owner == cid) {
+            boxes.remove(&cid.index());
+        }
+    }
+}
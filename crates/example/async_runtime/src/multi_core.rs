@@ -0,0 +1,245 @@
+//! 多核执行器之间共享的状态。
+//! 每个执行器只由所在线程通过 &mut 访问；其他线程只能接触这里的共享就绪队列和执行器的 WakeState，
+//! 它们只由原子变量和锁组成。
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize};
+use core::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use core::task::{Context, Waker};
+use spin::Mutex;
+use crate::executor::{WakeState, MAX_PRIO_NUM};
+
+/// 最多同时参与任务窃取的执行器数量
+pub const MAX_EXECUTOR_NUM: usize = 8;
+
+pub type SharedFuture = Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>>;
+
+/// 已注册执行器的唤醒状态，下标为执行器编号
+static WAKE_STATES: [AtomicPtr<WakeState>; MAX_EXECUTOR_NUM] = [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_EXECUTOR_NUM];
+
+/// 各执行器的共享就绪队列，下标为执行器编号
+static SHARED_QUEUES: [SharedQueue; MAX_EXECUTOR_NUM] = [const { SharedQueue::new() }; MAX_EXECUTOR_NUM];
+
+/// 尚未结束的共享协程数
+static SHARED_TASK_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// 既不在就绪队列中也没有在运行
+const IDLE: u8 = 0;
+/// 在某个执行器的共享就绪队列中
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+/// 运行期间被唤醒，本次 poll 返回后重新入队
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+/// 可在执行器之间迁移的协程。不在运行时总是位于某个执行器的共享就绪队列中或等待唤醒，
+/// 因此无论是否开始执行过都可以被其他执行器窃取。
+/// 共享协程没有 cid，只能使用基于 Waker 的原语（sync、定时器、JoinHandle 等），不能使用邮箱
+pub struct SharedTask {
+    future: Mutex<Option<SharedFuture>>,
+    prio: usize,
+    state: AtomicU8,
+    /// 最近一次运行它的执行器，被唤醒时回到该执行器的队列
+    home: AtomicUsize,
+}
+
+impl SharedTask {
+    /// 在 id 号执行器的共享就绪队列中生成协程
+    pub(crate) fn spawn(id: usize, future: SharedFuture, prio: usize) {
+        SHARED_TASK_NUM.fetch_add(1, Relaxed);
+        let task = Arc::new(Self {
+            future: Mutex::new(Some(future)),
+            prio,
+            state: AtomicU8::new(SCHEDULED),
+            home: AtomicUsize::new(id),
+        });
+        schedule(id, task);
+    }
+
+    #[inline]
+    pub fn prio(&self) -> usize {
+        self.prio
+    }
+
+    /// 在 id 号执行器上 poll 一次，只能由从就绪队列中取出该协程的执行器调用
+    pub(crate) fn run(self: Arc<Self>, id: usize) {
+        self.home.store(id, Relaxed);
+        self.state.store(RUNNING, Release);
+        let waker = Waker::from(self.clone());
+        let mut context = Context::from_waker(&waker);
+        let mut future = self.future.lock();
+        let res = future.as_mut().expect("finished shared coroutine was scheduled").as_mut().poll(&mut context);
+        if res.is_ready() {
+            *future = None;
+            drop(future);
+            self.state.store(DONE, Release);
+            if SHARED_TASK_NUM.fetch_sub(1, AcqRel) == 1 {
+                // 等待全部共享协程结束的执行器可能正在睡眠
+                notify_all();
+            }
+            return;
+        }
+        drop(future);
+        if self.state.compare_exchange(RUNNING, IDLE, AcqRel, Acquire).is_err() {
+            // 运行期间被唤醒
+            self.state.store(SCHEDULED, Release);
+            SHARED_QUEUES[id].push(self);
+        }
+    }
+}
+
+impl Wake for SharedTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Acquire);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match self.state.compare_exchange(state, next, AcqRel, Acquire) {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+        if state == IDLE {
+            schedule(self.home.load(Relaxed), self.clone());
+        }
+    }
+}
+
+/// 按优先级分级的共享就绪队列
+pub struct SharedQueue {
+    queues: Mutex<[VecDeque<Arc<SharedTask>>; MAX_PRIO_NUM]>,
+    len: AtomicUsize,
+}
+
+impl SharedQueue {
+    pub const fn new() -> Self {
+        Self {
+            queues: Mutex::new([const { VecDeque::new() }; MAX_PRIO_NUM]),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, task: Arc<SharedTask>) {
+        let prio = task.prio;
+        self.queues.lock()[prio].push_back(task);
+        self.len.fetch_add(1, Release);
+    }
+
+    /// 取出优先级最高的协程，其优先级不满足 accept 时留在队列中
+    fn pop_if(&self, accept: impl FnOnce(usize) -> bool) -> Option<Arc<SharedTask>> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut queues = self.queues.lock();
+        let prio = (0..MAX_PRIO_NUM).find(|&prio| !queues[prio].is_empty())?;
+        if !accept(prio) {
+            return None;
+        }
+        let task = queues[prio].pop_front();
+        self.len.fetch_sub(1, Release);
+        task
+    }
+
+    /// 窃取队列中一半（至少一个）的协程，优先取高优先级的
+    fn steal_half(&self) -> Vec<Arc<SharedTask>> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let mut queues = self.queues.lock();
+        let mut num = (self.len.load(Acquire) + 1) / 2;
+        let mut stolen = Vec::with_capacity(num);
+        for queue in queues.iter_mut() {
+            while num > 0 {
+                match queue.pop_front() {
+                    Some(task) => stolen.push(task),
+                    None => break,
+                }
+                num -= 1;
+            }
+        }
+        self.len.fetch_sub(stolen.len(), Release);
+        stolen
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len.load(Acquire) == 0
+    }
+}
+
+/// 把执行器注册为 id 号执行器，之后它可以与其他执行器互相窃取任务
+pub fn register_executor(id: usize, wake_state: &'static WakeState) {
+    assert!(id < MAX_EXECUTOR_NUM, "Executor id out of range");
+    WAKE_STATES[id].store(wake_state as *const WakeState as *mut WakeState, Release);
+}
+
+fn wake_state(id: usize) -> Option<&'static WakeState> {
+    // SAFETY: 注册的都是 &'static WakeState
+    unsafe { WAKE_STATES[id].load(Acquire).as_ref() }
+}
+
+/// 把协程放入 id 号执行器的队列。该执行器在睡眠时唤醒它，否则唤醒一个空闲的执行器来窃取
+fn schedule(id: usize, task: Arc<SharedTask>) {
+    SHARED_QUEUES[id].push(task);
+    if matches!(wake_state(id), Some(state) if state.notify_idle()) {
+        return;
+    }
+    for i in 1..MAX_EXECUTOR_NUM {
+        if matches!(wake_state((id + i) % MAX_EXECUTOR_NUM), Some(state) if state.notify_idle()) {
+            return;
+        }
+    }
+}
+
+fn notify_all() {
+    for id in 0..MAX_EXECUTOR_NUM {
+        if let Some(state) = wake_state(id) {
+            state.notify_idle();
+        }
+    }
+}
+
+/// 从 id 号执行器的共享队列中取出优先级满足 accept 的协程
+#[inline]
+pub fn pop_shared(id: usize, accept: impl FnOnce(usize) -> bool) -> Option<Arc<SharedTask>> {
+    SHARED_QUEUES[id].pop_if(accept)
+}
+
+/// 从其他执行器窃取协程：返回其中一个，其余放入 id 号执行器自己的队列
+pub fn steal_from_others(id: usize) -> Option<Arc<SharedTask>> {
+    for i in 1..MAX_EXECUTOR_NUM {
+        let victim = (id + i) % MAX_EXECUTOR_NUM;
+        let mut stolen = SHARED_QUEUES[victim].steal_half().into_iter();
+        if let Some(task) = stolen.next() {
+            for task in stolen {
+                SHARED_QUEUES[id].push(task);
+            }
+            return Some(task);
+        }
+    }
+    None
+}
+
+/// 是否有可以运行或窃取的共享协程
+pub fn has_shared_work() -> bool {
+    SHARED_QUEUES.iter().any(|queue| !queue.is_empty())
+}
+
+/// 所有共享协程是否都已结束
+#[inline]
+pub fn shared_tasks_finished() -> bool {
+    SHARED_TASK_NUM.load(Acquire) == 0
+}
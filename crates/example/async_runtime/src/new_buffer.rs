@@ -1,16 +1,35 @@
-use core::sync::atomic::AtomicBool;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use core::future::Future;
+use core::ops::Deref;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+use core::sync::atomic::Ordering::SeqCst;
+use core::task::{Context, Poll, Waker};
 use spin::Mutex;
 use crate::coroutine::CoroutineId;
-use crate::utils::SafeRingBuffer;
-use sel4::get_clock;
-use sel4::r#yield;
-use crate::utils::RingBuffer;
+use crate::executor::MAX_PRIO_NUM;
+use crate::queue::{LockWord, SpscQueue};
+use crate::payload::PayloadArena;
 
 pub const MAX_ITEM_NUM: usize = 4096;
 pub const MAX_IPC_MSG_LEN: usize = 16;
+/// msg_info 的低 16 位是消息标签；其上 4 位是调用者优先级加一，0 表示未携带；最高 12 位是请求编号
+const LABEL_MASK: u32 = 0xffff;
+const CALLER_PRIO_SHIFT: u32 = 16;
+const CALLER_PRIO_MASK: u32 = 0xf;
+const REQUEST_ID_SHIFT: u32 = 20;
+/// 请求编号的最大值
+pub const MAX_REQUEST_ID: u16 = (u32::MAX >> REQUEST_ID_SHIFT) as u16;
+/// 取消请求的标签：沿用被取消请求的 cid 与编号，服务端据此丢弃尚未完成的工作，不需要回复。
+/// 各服务的消息标签不能使用该值
+pub const CANCEL_LABEL: u32 = LABEL_MASK;
+/// 经 seL4_Call 发送时，完整的 seL4 MessageInfo 字（标签、cap 数、长度）放在 extend_msg 的前四个字，
+/// msg_info 中的编号与优先级位不受影响
+pub const MESSAGE_INFO_WORDS: usize = 4;
 
 #[repr(align(8))]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct IPCItem {
     pub cid: CoroutineId,
     pub msg_info: u32,
@@ -43,52 +62,320 @@ impl IPCItem {
             extend_msg: [0u16; MAX_IPC_MSG_LEN],
         }
     }
-}
 
-pub struct ItemsQueue {
-    buffer: SafeRingBuffer<IPCItem, MAX_ITEM_NUM>,
-    // lock: Mutex<()>,
+    /// 消息标签
+    #[inline]
+    pub fn label(&self) -> u32 {
+        self.msg_info & LABEL_MASK
+    }
+
+    /// 请求编号，0 表示未编号；回复沿用请求的编号
+    #[inline]
+    pub fn request_id(&self) -> u16 {
+        (self.msg_info >> REQUEST_ID_SHIFT) as u16
+    }
+
+    #[inline]
+    pub fn set_request_id(&mut self, id: u16) {
+        assert!(id <= MAX_REQUEST_ID, "request id out of range");
+        self.msg_info = self.msg_info & !((MAX_REQUEST_ID as u32) << REQUEST_ID_SHIFT) | (id as u32) << REQUEST_ID_SHIFT;
+    }
+
+    /// 发起请求的协程的优先级，服务端可以据此提升处理该请求的协程
+    #[inline]
+    pub fn caller_prio(&self) -> Option<usize> {
+        match (self.msg_info >> CALLER_PRIO_SHIFT) & CALLER_PRIO_MASK {
+            0 => None,
+            prio => Some(prio as usize - 1),
+        }
+    }
+
+    #[inline]
+    pub fn set_caller_prio(&mut self, prio: usize) {
+        assert!(prio < MAX_PRIO_NUM, "Priority out of range");
+        self.msg_info = self.msg_info & !(CALLER_PRIO_MASK << CALLER_PRIO_SHIFT) | (prio as u32 + 1) << CALLER_PRIO_SHIFT;
+    }
+
+    /// 写入完整的 seL4 MessageInfo 字，低位在前
+    #[inline]
+    pub fn set_message_info_word(&mut self, word: u64) {
+        for (i, part) in self.extend_msg[..MESSAGE_INFO_WORDS].iter_mut().enumerate() {
+            *part = (word >> (16 * i)) as u16;
+        }
+    }
+
+    #[inline]
+    pub fn message_info_word(&self) -> u64 {
+        self.extend_msg[..MESSAGE_INFO_WORDS].iter().rev().fold(0, |word, part| word << 16 | *part as u64)
+    }
+
+    /// 对 req 的回复：沿用请求的 cid 与编号，不携带优先级
+    pub fn reply_to(req: &IPCItem, label: u32) -> Self {
+        let mut item = Self::from(req.cid, label);
+        item.set_request_id(req.request_id());
+        item
+    }
+
+    /// 取消 req 的消息
+    #[inline]
+    pub fn cancel_of(req: &IPCItem) -> Self {
+        Self::reply_to(req, CANCEL_LABEL)
+    }
+
+    #[inline]
+    pub fn is_cancel(&self) -> bool {
+        self.label() == CANCEL_LABEL
+    }
 }
 
+/// 队列已满，元素未写入
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueFull;
 
+/// 本地址空间内因队列满而挂起的写者：队列地址 -> (写者登记号 -> waker)，每个 WriteItem 只占一项。
+/// 共享页中放不下 Waker，消费者取走元素时若发现队列原本已满，经 uipi 通知写者一侧
+static FULL_WAITERS: Mutex<BTreeMap<usize, BTreeMap<usize, Waker>>> = Mutex::new(BTreeMap::new());
+static FULL_WAITER_NUM: AtomicUsize = AtomicUsize::new(0);
+static NEXT_WAITER_KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// 共享页中的 IPCItem 队列，布局与内核一致（见 SpscQueue），全零内存即为空队列。
+/// 读写经 NewBuffer::req_queue/res_queue 取得的 SharedItems 进行，由共享页中的锁字互斥
+pub struct ItemsQueue {
+    buffer: SpscQueue<IPCItem, MAX_ITEM_NUM>,
+}
 
 impl ItemsQueue {
     pub fn new() -> Self {
         Self {
-            buffer: SafeRingBuffer::new(),
+            buffer: SpscQueue::new(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+impl Default for ItemsQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 一个队列的写者锁与读者锁，放在共享页中内核已知的部分之后，全零即为未上锁
+#[repr(C)]
+#[derive(Default)]
+pub struct QueueLocks {
+    write: LockWord,
+    read: LockWord,
+}
+
+/// 共享页中的队列及其锁字。同一侧的多个写者（读者）可以在不同地址空间、不同核上，
+/// 经锁字互斥后对 SpscQueue 而言只有一个生产者（消费者）
+#[derive(Clone, Copy)]
+pub struct SharedItems<'a> {
+    queue: &'a ItemsQueue,
+    locks: &'a QueueLocks,
+}
+
+impl<'a> SharedItems<'a> {
+    /// 非阻塞写入，队列满时返回 QueueFull
+    #[inline]
+    pub fn write_free_item(&self, item: &IPCItem) -> Result<(), QueueFull> {
+        let _guard = self.locks.write.lock();
+        self.queue.buffer.push(item).map(|_| ()).map_err(|_| QueueFull)
+    }
+
+    /// 写入元素，队列满时挂起当前协程，直到写者一侧调用 wake_writers
+    pub fn write_item(&self, item: &IPCItem) -> WriteItem<'a> {
+        WriteItem {
+            queue: *self,
+            item: *item,
+            key: None,
+        }
+    }
+
+    #[inline]
+    pub fn get_first_item(&self) -> Option<IPCItem> {
+        self.take_item().map(|(item, _)| item)
+    }
+
+    /// 取出一个元素，并返回取出之前队列是否已满。
+    /// 已满时可能有写者挂起在 write_item 上，需要经 uipi 通知写者一侧调用 wake_writers
+    #[inline]
+    pub fn take_item(&self) -> Option<(IPCItem, bool)> {
+        let _guard = self.locks.read.lock();
+        self.queue.buffer.take().map(|(item, len)| (item, len == MAX_ITEM_NUM))
+    }
+
+    /// 写者一侧收到通知后调用，唤醒本地址空间内所有挂起在该队列上的写者
+    pub fn wake_writers(&self) {
+        if FULL_WAITER_NUM.load(SeqCst) == 0 {
+            return;
+        }
+        let waiters = FULL_WAITERS.lock().remove(&self.addr());
+        if let Some(waiters) = waiters {
+            FULL_WAITER_NUM.fetch_sub(waiters.len(), SeqCst);
+            for waker in waiters.into_values() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// 本地址空间内挂起在该队列上的写者个数
+    pub fn waiting_writer_num(&self) -> usize {
+        FULL_WAITERS.lock().get(&self.addr()).map_or(0, |waiters| waiters.len())
+    }
+
+    fn register_writer(&self, key: usize, waker: &Waker) {
+        let mut waiters = FULL_WAITERS.lock();
+        match waiters.entry(self.addr()).or_default().entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(waker.clone());
+                FULL_WAITER_NUM.fetch_add(1, SeqCst);
+            }
+            Entry::Occupied(mut entry) => {
+                if !entry.get().will_wake(waker) {
+                    entry.insert(waker.clone());
+                }
+            }
+        }
+    }
+
+    fn deregister_writer(&self, key: usize) {
+        let mut waiters = FULL_WAITERS.lock();
+        if let Some(queue_waiters) = waiters.get_mut(&self.addr()) {
+            if queue_waiters.remove(&key).is_some() {
+                FULL_WAITER_NUM.fetch_sub(1, SeqCst);
+            }
+            if queue_waiters.is_empty() {
+                waiters.remove(&self.addr());
+            }
         }
     }
 
     #[inline]
-    pub fn write_free_item(&mut self, item: &IPCItem) -> Result<(), ()> {
-        return self.buffer.push_safe(item);
+    fn addr(&self) -> usize {
+        self.queue as *const ItemsQueue as usize
     }
 
     #[inline]
-    pub fn get_first_item(&mut self) -> Option<IPCItem> {
-        return self.buffer.pop_safe();
+    pub fn len(&self) -> usize {
+        self.queue.len()
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// 向队列写入一个元素的 Future。挂起期间在等待表中只占一项，重复 poll 时替换其中的 waker，
+/// 被丢弃时注销
+pub struct WriteItem<'a> {
+    queue: SharedItems<'a>,
+    item: IPCItem,
+    /// 在等待表中的登记号，第一次挂起时分配
+    key: Option<usize>,
+}
+
+impl WriteItem<'_> {
+    fn finish(&mut self) -> Poll<()> {
+        if let Some(key) = self.key.take() {
+            self.queue.deregister_writer(key);
+        }
+        Poll::Ready(())
+    }
 }
 
+impl Future for WriteItem<'_> {
+    type Output = ();
 
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let queue = this.queue;
+        if queue.write_free_item(&this.item).is_ok() {
+            return this.finish();
+        }
+        let key = *this.key.get_or_insert_with(|| NEXT_WAITER_KEY.fetch_add(1, SeqCst));
+        queue.register_writer(key, cx.waker());
+        // 登记之前消费者可能已经取走了元素，重试一次避免丢失唤醒；之后取走元素的消费者
+        // 必然看到队列已满并发出通知
+        if queue.write_free_item(&this.item).is_ok() {
+            return this.finish();
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for WriteItem<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.queue.deregister_writer(key);
+        }
+    }
+}
+
+/// 共享页中内核已知的部分。字段类型、声明顺序与 repr 都与内核中的 NewBuffer 相同，不能改动
 #[repr(align(4096))]
-pub struct NewBuffer {
+pub struct KernelBuffer {
     pub recv_req_status: AtomicBool,
     pub recv_reply_status: AtomicBool,
     pub req_items: ItemsQueue,
     pub res_items: ItemsQueue,
 }
 
+/// 客户端与服务端（或内核）共享的一页缓冲区。
+///
+/// 内核已知的部分在最前面，经 Deref 访问；之后的字段只在用户态之间使用，内核不访问
+#[repr(C)]
+pub struct NewBuffer {
+    kernel: KernelBuffer,
+    req_locks: QueueLocks,
+    res_locks: QueueLocks,
+    /// 超出 extend_msg 的数据放在这里，IPCItem 中只携带区段描述符
+    pub payload: PayloadArena,
+}
+
 impl NewBuffer {
     pub fn new() -> Self {
         Self {
-            recv_req_status: AtomicBool::new(false),
-            recv_reply_status: AtomicBool::new(false),
-            req_items: ItemsQueue::new(),
-            res_items: ItemsQueue::new(),
+            kernel: KernelBuffer {
+                recv_req_status: AtomicBool::new(false),
+                recv_reply_status: AtomicBool::new(false),
+                req_items: ItemsQueue::new(),
+                res_items: ItemsQueue::new(),
+            },
+            req_locks: QueueLocks::default(),
+            res_locks: QueueLocks::default(),
+            payload: PayloadArena::new(),
+        }
+    }
+
+    /// 请求队列
+    #[inline]
+    pub fn req_queue(&self) -> SharedItems<'_> {
+        SharedItems {
+            queue: &self.kernel.req_items,
+            locks: &self.req_locks,
+        }
+    }
+
+    /// 回复队列
+    #[inline]
+    pub fn res_queue(&self) -> SharedItems<'_> {
+        SharedItems {
+            queue: &self.kernel.res_items,
+            locks: &self.res_locks,
         }
     }
+
     #[inline]
     pub fn get_ptr(&self) -> usize {
         self as *const Self as usize
@@ -100,4 +387,19 @@ impl NewBuffer {
             &mut *(ptr as *mut Self)
         }
     }
-}
\ No newline at end of file
+}
+
+impl Deref for NewBuffer {
+    type Target = KernelBuffer;
+
+    #[inline]
+    fn deref(&self) -> &KernelBuffer {
+        &self.kernel
+    }
+}
+
+impl Default for NewBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
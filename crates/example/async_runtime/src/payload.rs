@@ -0,0 +1,375 @@
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ptr;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use crate::new_buffer::{IPCItem, MAX_IPC_MSG_LEN};
+
+/// 负载区的分配粒度
+pub const PAYLOAD_BLOCK_SIZE: usize = 256;
+/// 每个 NewBuffer 通道附带的负载区大小
+pub const PAYLOAD_ARENA_SIZE: usize = 64 * 4096;
+const PAYLOAD_BLOCK_NUM: usize = PAYLOAD_ARENA_SIZE / PAYLOAD_BLOCK_SIZE;
+const PAYLOAD_BITMAP_WORDS: usize = PAYLOAD_BLOCK_NUM / 64;
+/// 一个 IPCItem 最多携带的负载区段数
+pub const MAX_PAYLOAD_SEGMENTS: usize = 3;
+/// 每个区段描述符在 extend_msg 中占用的字数
+const DESC_WORDS: usize = 4;
+
+/// 负载区中的一段数据，offset 相对负载区起始位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadDesc {
+    pub offset: u32,
+    pub len: u32,
+}
+
+impl PayloadDesc {
+    #[inline]
+    fn end(&self) -> Option<usize> {
+        (self.offset as usize).checked_add(self.len as usize)
+    }
+}
+
+/// 分散/聚集的区段列表
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadList {
+    descs: [PayloadDesc; MAX_PAYLOAD_SEGMENTS],
+    num: usize,
+}
+
+impl PayloadList {
+    pub const fn new() -> Self {
+        Self {
+            descs: [PayloadDesc { offset: 0, len: 0 }; MAX_PAYLOAD_SEGMENTS],
+            num: 0,
+        }
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn push(&mut self, desc: PayloadDesc) -> Result<(), ()> {
+        if self.num == MAX_PAYLOAD_SEGMENTS {
+            return Err(());
+        }
+        self.descs[self.num] = desc;
+        self.num += 1;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[PayloadDesc] {
+        &self.descs[..self.num]
+    }
+
+    /// 所有区段的总字节数
+    pub fn len(&self) -> usize {
+        self.as_slice().iter().map(|desc| desc.len as usize).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.num == 0
+    }
+}
+
+impl IPCItem {
+    /// 把 32 位的值写入 extend_msg[first_word] 与其后一个字，低 16 位在前。
+    /// 负载长度可以超过 u16::MAX，消息中的长度字段用它编码
+    #[inline]
+    pub fn set_word32(&mut self, first_word: usize, value: u32) {
+        self.extend_msg[first_word] = value as u16;
+        self.extend_msg[first_word + 1] = (value >> 16) as u16;
+    }
+
+    #[inline]
+    pub fn get_word32(&self, first_word: usize) -> u32 {
+        self.extend_msg[first_word] as u32 | (self.extend_msg[first_word + 1] as u32) << 16
+    }
+
+    /// 从 extend_msg[first_word] 开始写入区段列表：先写区段数，再依次写每个区段的 offset 与 len
+    #[allow(clippy::result_unit_err)]
+    pub fn set_payload(&mut self, first_word: usize, list: &PayloadList) -> Result<(), ()> {
+        let words = self.extend_msg.get_mut(first_word..).ok_or(())?;
+        if words.len() < 1 + list.num * DESC_WORDS {
+            return Err(());
+        }
+        words[0] = list.num as u16;
+        for (desc, words) in list.as_slice().iter().zip(words[1..].chunks_mut(DESC_WORDS)) {
+            words[0] = desc.offset as u16;
+            words[1] = (desc.offset >> 16) as u16;
+            words[2] = desc.len as u16;
+            words[3] = (desc.len >> 16) as u16;
+        }
+        Ok(())
+    }
+
+    /// 读出 set_payload 写入的区段列表。区段数来自对端，越界时返回 Err；
+    /// 区段本身是否落在负载区内由 PayloadArena 在访问时检查
+    #[allow(clippy::result_unit_err)]
+    pub fn get_payload(&self, first_word: usize) -> Result<PayloadList, ()> {
+        let words = self.extend_msg.get(first_word..).ok_or(())?;
+        let num = *words.first().ok_or(())? as usize;
+        if num > MAX_PAYLOAD_SEGMENTS || words.len() < 1 + num * DESC_WORDS {
+            return Err(());
+        }
+        let mut list = PayloadList::new();
+        for words in words[1..].chunks(DESC_WORDS).take(num) {
+            list.push(PayloadDesc {
+                offset: words[0] as u32 | (words[1] as u32) << 16,
+                len: words[2] as u32 | (words[3] as u32) << 16,
+            })?;
+        }
+        Ok(list)
+    }
+}
+
+/// 区段数占一个字，之后是各区段的描述符，整个列表要能放进一条 IPC 消息
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(MAX_PAYLOAD_SEGMENTS * DESC_WORDS < MAX_IPC_MSG_LEN);
+
+/// 通道双方共享的负载区，以 PAYLOAD_BLOCK_SIZE 为粒度分配。
+/// 由请求方分配、写入并在收到回复后释放，服务方只按描述符读写。
+/// 与队列一样，全零内存即为空的负载区
+#[repr(C)]
+pub struct PayloadArena {
+    lock: AtomicBool,
+    /// 已分配的块，置位表示占用
+    used: UnsafeCell<[u64; PAYLOAD_BITMAP_WORDS]>,
+    data: UnsafeCell<[u8; PAYLOAD_ARENA_SIZE]>,
+}
+
+unsafe impl Sync for PayloadArena {}
+
+impl PayloadArena {
+    pub fn new() -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            used: UnsafeCell::new([0; PAYLOAD_BITMAP_WORDS]),
+            data: UnsafeCell::new([0; PAYLOAD_ARENA_SIZE]),
+        }
+    }
+
+    /// 分配 len 字节。优先分配连续的一段，碎片化时最多拆成 MAX_PAYLOAD_SEGMENTS 段
+    #[allow(clippy::result_unit_err)]
+    pub fn alloc(&self, len: usize) -> Result<PayloadList, ()> {
+        if len == 0 || len > PAYLOAD_ARENA_SIZE {
+            return Err(());
+        }
+        let blocks = (len + PAYLOAD_BLOCK_SIZE - 1) / PAYLOAD_BLOCK_SIZE;
+        self.with_bitmap(|used: &mut [u64; PAYLOAD_BITMAP_WORDS]| {
+            let mut list = PayloadList::new();
+            if let Some(start) = find_free_run(used, blocks) {
+                mark(used, start, blocks, true);
+                list.push(block_desc(start, len))?;
+                return Ok(list);
+            }
+            // 没有足够长的连续空闲块，按顺序取空闲段拼起来
+            let mut left = len;
+            let mut start = 0;
+            while left > 0 && start < PAYLOAD_BLOCK_NUM {
+                if is_used(used, start) {
+                    start += 1;
+                    continue;
+                }
+                let mut run = 0;
+                while start + run < PAYLOAD_BLOCK_NUM && !is_used(used, start + run) && run * PAYLOAD_BLOCK_SIZE < left {
+                    run += 1;
+                }
+                let seg_len = left.min(run * PAYLOAD_BLOCK_SIZE);
+                if list.push(block_desc(start, seg_len)).is_err() {
+                    break;
+                }
+                mark(used, start, run, true);
+                left -= seg_len;
+                start += run;
+            }
+            if left > 0 {
+                for desc in list.as_slice() {
+                    mark(used, desc.offset as usize / PAYLOAD_BLOCK_SIZE, desc_blocks(desc), false);
+                }
+                return Err(());
+            }
+            Ok(list)
+        })
+    }
+
+    /// 分配 len 字节，返回的 PayloadLease 被释放时归还这些区段
+    #[allow(clippy::result_unit_err)]
+    pub fn lease(&'static self, len: usize) -> Result<PayloadLease, ()> {
+        Ok(PayloadLease {
+            arena: self,
+            list: self.alloc(len)?,
+        })
+    }
+
+    /// 释放 alloc 得到的区段列表
+    #[allow(clippy::result_unit_err)]
+    pub fn free(&self, list: &PayloadList) -> Result<(), ()> {
+        for desc in list.as_slice() {
+            self.validate(desc)?;
+            if desc.offset as usize % PAYLOAD_BLOCK_SIZE != 0 {
+                return Err(());
+            }
+        }
+        self.with_bitmap(|used: &mut [u64; PAYLOAD_BITMAP_WORDS]| {
+            for desc in list.as_slice() {
+                mark(used, desc.offset as usize / PAYLOAD_BLOCK_SIZE, desc_blocks(desc), false);
+            }
+        });
+        Ok(())
+    }
+
+    /// 检查对端给出的区段是否落在负载区内
+    #[inline]
+    #[allow(clippy::result_unit_err)]
+    pub fn validate(&self, desc: &PayloadDesc) -> Result<(), ()> {
+        match desc.end() {
+            Some(end) if end <= PAYLOAD_ARENA_SIZE => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// 区段的起始地址。
+    /// 负载区位于共享页中，对端可能同时访问，因此只提供裸指针、不借出引用；
+    /// 读写应通过 read_segment / write_segment 等按字节拷贝的方式进行
+    #[allow(clippy::result_unit_err)]
+    pub fn segment_ptr(&self, desc: &PayloadDesc) -> Result<*mut u8, ()> {
+        self.validate(desc)?;
+        Ok(self.data.get().cast::<u8>().wrapping_add(desc.offset as usize))
+    }
+
+    /// 区段内 [offset, offset + len) 的起始地址，超出区段时返回 Err
+    fn segment_range(&self, desc: &PayloadDesc, offset: usize, len: usize) -> Result<*mut u8, ()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= desc.len as usize => Ok(self.segment_ptr(desc)?.wrapping_add(offset)),
+            _ => Err(()),
+        }
+    }
+
+    /// 从区段内 offset 处开始读满 dst
+    #[allow(clippy::result_unit_err)]
+    pub fn read_segment(&self, desc: &PayloadDesc, offset: usize, dst: &mut [u8]) -> Result<(), ()> {
+        let src = self.segment_range(desc, offset, dst.len())?;
+        // SAFETY: 范围已检查落在负载区内，dst 是调用者的内存，不会与负载区重叠
+        unsafe { ptr::copy_nonoverlapping(src, dst.as_mut_ptr(), dst.len()) };
+        Ok(())
+    }
+
+    /// 把 src 写入区段内 offset 处
+    #[allow(clippy::result_unit_err)]
+    pub fn write_segment(&self, desc: &PayloadDesc, offset: usize, src: &[u8]) -> Result<(), ()> {
+        let dst = self.segment_range(desc, offset, src.len())?;
+        // SAFETY: 同 read_segment
+        unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) };
+        Ok(())
+    }
+
+    /// 把 src 依次写入各区段，返回写入的字节数
+    #[allow(clippy::result_unit_err)]
+    pub fn write(&self, list: &PayloadList, src: &[u8]) -> Result<usize, ()> {
+        let mut copied = 0;
+        for desc in list.as_slice() {
+            self.validate(desc)?;
+            let n = (desc.len as usize).min(src.len() - copied);
+            self.write_segment(desc, 0, &src[copied..copied + n])?;
+            copied += n;
+        }
+        Ok(copied)
+    }
+
+    /// 把各区段依次读到 dst，返回读出的字节数
+    #[allow(clippy::result_unit_err)]
+    pub fn read(&self, list: &PayloadList, dst: &mut [u8]) -> Result<usize, ()> {
+        let mut copied = 0;
+        for desc in list.as_slice() {
+            self.validate(desc)?;
+            let n = (desc.len as usize).min(dst.len() - copied);
+            self.read_segment(desc, 0, &mut dst[copied..copied + n])?;
+            copied += n;
+        }
+        Ok(copied)
+    }
+
+    fn with_bitmap<R>(&self, f: impl FnOnce(&mut [u64; PAYLOAD_BITMAP_WORDS]) -> R) -> R {
+        while self.lock.compare_exchange_weak(false, true, Acquire, Relaxed).is_err() {
+            spin_loop();
+        }
+        let ret = f(unsafe { &mut *self.used.get() });
+        self.lock.store(false, Release);
+        ret
+    }
+}
+
+impl Default for PayloadArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 一次调用占用的负载区段，被释放时归还负载区。
+/// 请求已送出时不能随调用一起释放，见 PendingCalls::abandon
+pub struct PayloadLease {
+    arena: &'static PayloadArena,
+    list: PayloadList,
+}
+
+impl PayloadLease {
+    #[inline]
+    pub fn list(&self) -> &PayloadList {
+        &self.list
+    }
+
+    #[inline]
+    pub fn arena(&self) -> &'static PayloadArena {
+        self.arena
+    }
+}
+
+impl Drop for PayloadLease {
+    fn drop(&mut self) {
+        // 区段由 alloc 分配，不会释放失败
+        let _ = self.arena.free(&self.list);
+    }
+}
+
+#[inline]
+fn is_used(used: &[u64; PAYLOAD_BITMAP_WORDS], block: usize) -> bool {
+    used[block >> 6] & (1 << (block & 0b0011_1111)) != 0
+}
+
+fn mark(used: &mut [u64; PAYLOAD_BITMAP_WORDS], start: usize, num: usize, value: bool) {
+    for block in start..start + num {
+        if value {
+            used[block >> 6] |= 1 << (block & 0b0011_1111);
+        } else {
+            used[block >> 6] &= !(1 << (block & 0b0011_1111));
+        }
+    }
+}
+
+fn find_free_run(used: &[u64; PAYLOAD_BITMAP_WORDS], num: usize) -> Option<usize> {
+    let mut run = 0;
+    for block in 0..PAYLOAD_BLOCK_NUM {
+        if is_used(used, block) {
+            run = 0;
+        } else {
+            run += 1;
+            if run == num {
+                return Some(block + 1 - num);
+            }
+        }
+    }
+    None
+}
+
+#[inline]
+fn block_desc(start: usize, len: usize) -> PayloadDesc {
+    PayloadDesc {
+        offset: (start * PAYLOAD_BLOCK_SIZE) as u32,
+        len: len as u32,
+    }
+}
+
+#[inline]
+fn desc_blocks(desc: &PayloadDesc) -> usize {
+    (desc.len as usize + PAYLOAD_BLOCK_SIZE - 1) / PAYLOAD_BLOCK_SIZE
+}
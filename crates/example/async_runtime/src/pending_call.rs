@@ -0,0 +1,203 @@
+use alloc::collections::BTreeMap;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use crate::coroutine::CoroutineId;
+use crate::new_buffer::{IPCItem, MAX_REQUEST_ID};
+use crate::payload::PayloadLease;
+
+/// 回复无法投递的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplyError {
+    /// 没有对应编号的未完成调用：编号错误，或调用已经结束或被取消
+    UnknownRequest,
+    /// 回复的 cid 与发起调用的协程不符
+    WrongCoroutine,
+    /// 该调用已经收到过回复
+    Duplicate,
+    /// 调用已被放弃，回复或取消确认到达后释放了它占用的负载区段
+    Abandoned,
+}
+
+struct Call {
+    cid: CoroutineId,
+    reply: Option<IPCItem>,
+    waker: Option<Waker>,
+    /// 被放弃的调用仍占用的负载区段，服务端回复或确认取消后才能释放
+    abandoned: Option<PayloadLease>,
+}
+
+struct PendingInner {
+    calls: BTreeMap<u16, Call>,
+    next_id: u16,
+    dropped_num: usize,
+}
+
+/// 未完成的调用表：每个请求分配一个编号，回复按编号投递给对应的等待者，
+/// 因此同一协程可以同时发起多个调用，错投或重复的回复会被发现并丢弃
+pub struct PendingCalls {
+    inner: Mutex<PendingInner>,
+}
+
+impl PendingCalls {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(PendingInner {
+                calls: BTreeMap::new(),
+                next_id: 1,
+                dropped_num: 0,
+            })
+        }
+    }
+
+    /// 为协程 cid 的一次调用分配编号，编号用尽时返回 Err
+    #[allow(clippy::result_unit_err)]
+    pub fn register(&self, cid: &CoroutineId) -> Result<u16, ()> {
+        let mut inner = self.inner.lock();
+        if inner.calls.len() >= MAX_REQUEST_ID as usize {
+            return Err(());
+        }
+        let mut id = inner.next_id;
+        while inner.calls.contains_key(&id) {
+            id = next_request_id(id);
+        }
+        inner.next_id = next_request_id(id);
+        inner.calls.insert(id, Call {
+            cid: *cid,
+            reply: None,
+            waker: None,
+            abandoned: None,
+        });
+        Ok(id)
+    }
+
+    /// 等待编号为 id 的回复。返回的 future 被释放时注销该调用，之后到达的回复会被丢弃
+    pub fn wait(&self, id: u16) -> ReplyFuture<'_> {
+        ReplyFuture {
+            calls: self,
+            id,
+            done: false,
+        }
+    }
+
+    /// 注销调用，之后到达的回复会被丢弃。被放弃的调用保留到回复或取消确认到达
+    pub fn cancel(&self, id: u16) {
+        let mut inner = self.inner.lock();
+        if matches!(inner.calls.get(&id), Some(call) if call.abandoned.is_none()) {
+            inner.calls.remove(&id);
+        }
+    }
+
+    /// 放弃已送出、尚未收到回复的调用。服务端此时可能仍在访问 lease 中的区段，
+    /// 因此保留该编号和 lease，直到服务端的回复或取消确认到达时再释放
+    pub fn abandon(&self, id: u16, lease: PayloadLease) {
+        let mut inner = self.inner.lock();
+        if let Some(call) = inner.calls.get_mut(&id) {
+            if call.reply.is_none() {
+                call.abandoned = Some(lease);
+                call.waker = None;
+                return;
+            }
+        }
+        // 回复已经到达，服务端不会再访问这些区段；编号由等待者的 ReplyFuture 注销
+        drop(inner);
+        drop(lease);
+    }
+
+    /// 投递回复并唤醒等待者。取消确认只用于结束被放弃的调用
+    pub fn complete(&self, reply: &IPCItem) -> Result<(), ReplyError> {
+        let mut inner = self.inner.lock();
+        let ret = match inner.calls.get_mut(&reply.request_id()) {
+            None => Err(ReplyError::UnknownRequest),
+            Some(call) if call.cid != reply.cid => Err(ReplyError::WrongCoroutine),
+            Some(call) if call.abandoned.is_some() => {
+                let call = inner.calls.remove(&reply.request_id());
+                inner.dropped_num += 1;
+                drop(inner);
+                // 在锁外释放负载区段
+                drop(call);
+                return Err(ReplyError::Abandoned);
+            }
+            Some(_) if reply.is_cancel() => Err(ReplyError::UnknownRequest),
+            Some(call) if call.reply.is_some() => Err(ReplyError::Duplicate),
+            Some(call) => {
+                call.reply = Some(*reply);
+                Ok(call.waker.take())
+            }
+        };
+        match ret {
+            Ok(waker) => {
+                drop(inner);
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+                Ok(())
+            }
+            Err(err) => {
+                inner.dropped_num += 1;
+                Err(err)
+            }
+        }
+    }
+
+    /// 未完成的调用数
+    pub fn len(&self) -> usize {
+        self.inner.lock().calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 被丢弃的错投、重复或迟到的回复数
+    pub fn dropped_num(&self) -> usize {
+        self.inner.lock().dropped_num
+    }
+}
+
+/// 编号在 1..=MAX_REQUEST_ID 中循环使用，0 保留给未编号的消息
+fn next_request_id(id: u16) -> u16 {
+    if id >= MAX_REQUEST_ID { 1 } else { id + 1 }
+}
+
+pub struct ReplyFuture<'a> {
+    calls: &'a PendingCalls,
+    id: u16,
+    /// 已取得回复，编号可能已经分配给新的调用，释放时不能再注销
+    done: bool,
+}
+
+impl ReplyFuture<'_> {
+    /// 调用的编号
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+impl Future for ReplyFuture<'_> {
+    type Output = IPCItem;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.done, "reply future polled after completion");
+        let calls = self.calls;
+        let mut inner = calls.inner.lock();
+        let call = inner.calls.get_mut(&self.id).expect("pending call cancelled while waiting for its reply");
+        if let Some(reply) = call.reply {
+            inner.calls.remove(&self.id);
+            drop(inner);
+            self.done = true;
+            return Poll::Ready(reply);
+        }
+        call.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for ReplyFuture<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.calls.cancel(self.id);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+extern crate std;
+
+use core::cell::Cell;
+use core::fmt;
+use std::boxed::Box;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use super::Platform;
+
+std::thread_local! {
+    static EXECUTOR: Cell<usize> = Cell::new(0);
+}
+
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// 宿主机上模拟 seL4 notification：signal 会被记住，直到下一次 wait 取走
+pub struct HostNotification {
+    signalled: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl HostNotification {
+    /// 新建通知对象，对象在程序运行期间不会被释放
+    pub fn new() -> &'static Self {
+        Box::leak(Box::new(Self {
+            signalled: Mutex::new(false),
+            cond: Condvar::new(),
+        }))
+    }
+
+    pub fn signal(&self) {
+        *self.signalled.lock().unwrap() = true;
+        self.cond.notify_one();
+    }
+
+    pub fn wait(&self) {
+        let mut signalled = self.signalled.lock().unwrap();
+        while !*signalled {
+            signalled = self.cond.wait(signalled).unwrap();
+        }
+        *signalled = false;
+    }
+
+    /// 最多等待 timeout，被 signal 时返回 true
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let signalled = self.signalled.lock().unwrap();
+        let (mut signalled, _) = self.cond.wait_timeout_while(signalled, timeout, |signalled| !*signalled).unwrap();
+        core::mem::replace(&mut *signalled, false)
+    }
+}
+
+pub struct HostPlatform;
+
+impl Platform for HostPlatform {
+    type Notification = &'static HostNotification;
+
+    /// 以纳秒为时钟周期
+    #[inline]
+    fn clock() -> u64 {
+        START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+    }
+
+    #[inline]
+    fn yield_now() {
+        std::thread::yield_now();
+    }
+
+    fn print(args: fmt::Arguments) {
+        std::print!("{}", args);
+    }
+
+    #[inline]
+    fn executor_ptr() -> usize {
+        EXECUTOR.with(|executor| executor.get())
+    }
+
+    #[inline]
+    fn set_executor_ptr(ptr: usize) {
+        EXECUTOR.with(|executor| executor.set(ptr));
+    }
+
+    #[inline]
+    fn signal(ntfn: &Self::Notification) {
+        ntfn.signal();
+    }
+
+    #[inline]
+    fn wait(ntfn: &Self::Notification) {
+        ntfn.wait();
+    }
+
+    #[inline]
+    fn wait_until(ntfn: &Self::Notification, deadline: u64) {
+        ntfn.wait_timeout(Duration::from_nanos(deadline.saturating_sub(Self::clock())));
+    }
+}
@@ -0,0 +1,68 @@
+//! 运行时依赖的平台接口。
+//! 目标机上由 seL4 实现；启用 std 特性并关闭 sel4 特性时使用宿主机实现，以便在 Linux 上运行测试。
+
+use core::fmt;
+
+#[cfg(feature = "sel4")]
+mod sel4;
+#[cfg(feature = "sel4")]
+pub use self::sel4::Sel4Platform as CurrentPlatform;
+
+#[cfg(all(feature = "std", not(feature = "sel4")))]
+mod host;
+#[cfg(all(feature = "std", not(feature = "sel4")))]
+pub use self::host::{HostNotification, HostPlatform as CurrentPlatform};
+
+#[cfg(not(any(feature = "sel4", feature = "std")))]
+compile_error!("async_runtime needs either the `sel4` or the `std` feature");
+
+pub trait Platform {
+    /// 可在其他线程或中断中 signal、由执行器线程阻塞等待的通知对象
+    type Notification: Copy;
+
+    /// 单调递增的时钟周期数
+    fn clock() -> u64;
+
+    /// 让出 CPU
+    fn yield_now();
+
+    /// 输出调试信息
+    fn print(args: fmt::Arguments);
+
+    /// 当前线程的执行器地址，尚未初始化时为 0
+    fn executor_ptr() -> usize;
+
+    fn set_executor_ptr(ptr: usize);
+
+    /// 唤醒阻塞在 ntfn 上的线程，没有线程阻塞时记住本次 signal
+    fn signal(ntfn: &Self::Notification);
+
+    /// 阻塞直到 ntfn 被 signal
+    fn wait(ntfn: &Self::Notification);
+
+    /// 阻塞直到 ntfn 被 signal 或时钟到达 deadline，允许提前返回，调用者需要自行检查等待条件
+    fn wait_until(ntfn: &Self::Notification, deadline: u64);
+}
+
+pub type Notification = <CurrentPlatform as Platform>::Notification;
+
+#[inline]
+pub fn get_clock() -> u64 {
+    CurrentPlatform::clock()
+}
+
+#[inline]
+pub fn r#yield() {
+    CurrentPlatform::yield_now()
+}
+
+#[inline]
+pub fn print(args: fmt::Arguments) {
+    CurrentPlatform::print(args)
+}
+
+macro_rules! debug_println {
+    ($($arg:tt)*) => {
+        $crate::platform::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
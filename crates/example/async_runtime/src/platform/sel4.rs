@@ -0,0 +1,53 @@
+use core::fmt;
+use super::Platform;
+
+#[thread_local]
+static mut EXECUTOR: usize = 0;
+
+pub struct Sel4Platform;
+
+impl Platform for Sel4Platform {
+    type Notification = sel4::Notification;
+
+    #[inline]
+    fn clock() -> u64 {
+        sel4::get_clock()
+    }
+
+    #[inline]
+    fn yield_now() {
+        sel4::r#yield()
+    }
+
+    #[inline]
+    fn print(args: fmt::Arguments) {
+        sel4::debug_print!("{}", args);
+    }
+
+    #[inline]
+    fn executor_ptr() -> usize {
+        unsafe { EXECUTOR }
+    }
+
+    #[inline]
+    fn set_executor_ptr(ptr: usize) {
+        unsafe { EXECUTOR = ptr; }
+    }
+
+    #[inline]
+    fn signal(ntfn: &Self::Notification) {
+        ntfn.signal();
+    }
+
+    #[inline]
+    fn wait(ntfn: &Self::Notification) {
+        let _ = ntfn.wait();
+    }
+
+    /// seL4 的 notification 等待不能设置超时：轮询一次后让出 CPU 即返回，由调用者循环到 deadline
+    #[inline]
+    fn wait_until(ntfn: &Self::Notification, _deadline: u64) {
+        let _ = ntfn.poll();
+        sel4::r#yield();
+    }
+}
@@ -0,0 +1,175 @@
+//! 跨地址空间、跨核共享的有界环形队列。
+//!
+//! 队列直接放在共享页中（例如 alloc_zeroed 分配后映射给内核或其他进程的 NewBuffer），
+//! 因此全零的内存就是一个合法的空队列，不需要调用 new 初始化。
+
+#[cfg(not(loom))]
+use core::hint::spin_loop;
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+#[cfg(not(loom))]
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+#[cfg(loom)]
+use loom::hint::spin_loop;
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicBool, AtomicUsize};
+#[cfg(loom)]
+use loom::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+#[cfg(loom)]
+use loom::cell::UnsafeCell;
+
+/// 与 loom::cell::UnsafeCell 接口一致的 UnsafeCell，使队列代码可以直接交给 loom 做模型检查
+#[cfg(not(loom))]
+#[repr(transparent)]
+struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(value: T) -> Self {
+        Self(core::cell::UnsafeCell::new(value))
+    }
+
+    #[inline]
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    #[inline]
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// 单生产者单消费者环形队列。
+///
+/// 与内核中的 SafeRingBuffer 字段类型、声明顺序相同（`data: [T; SIZE]`、`start`、`end`、`count`），
+/// 也同样使用默认 repr，内核异步系统调用通道按这个布局读写共享页，不能改动。
+/// 生产者写入 `data[end]` 后推进 `end`，再增加 `count` 发布元素；
+/// 消费者看到 `count` 非零后才读 `data[start]`，读完推进 `start` 再减少 `count` 归还槽位。
+/// `count` 的读改写使用 SeqCst，使两侧都能据其前值判断队列在操作前是否为空或已满。
+/// 同一侧有多个生产者（或消费者）时，调用者需要用 LockWord 互斥。
+pub struct SpscQueue<T, const SIZE: usize> {
+    data: [UnsafeCell<T>; SIZE],
+    /// 消费者下一次读取的位置
+    start: AtomicUsize,
+    /// 生产者下一次写入的位置
+    end: AtomicUsize,
+    /// 已发布的元素个数
+    count: AtomicUsize,
+}
+
+unsafe impl<T: Send, const SIZE: usize> Sync for SpscQueue<T, SIZE> {}
+
+impl<T: Copy + Default, const SIZE: usize> SpscQueue<T, SIZE> {
+    pub fn new() -> Self {
+        Self {
+            data: core::array::from_fn(|_| UnsafeCell::new(T::default())),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Copy + Default, const SIZE: usize> Default for SpscQueue<T, SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const SIZE: usize> SpscQueue<T, SIZE> {
+    /// 只能由唯一的生产者调用。成功时返回写入之前的元素个数，队列满时返回 Err
+    #[inline]
+    #[allow(clippy::result_unit_err)]
+    pub fn push(&self, item: &T) -> Result<usize, ()> {
+        if self.count.load(SeqCst) == SIZE {
+            return Err(());
+        }
+        let end = self.end.load(Relaxed);
+        // SAFETY: count < SIZE，data[end] 不在已发布的范围内，消费者不会读它
+        self.data[end].with_mut(|slot| unsafe { *slot = *item });
+        self.end.store((end + 1) % SIZE, Relaxed);
+        Ok(self.count.fetch_add(1, SeqCst))
+    }
+
+    /// 只能由唯一的消费者调用。返回元素和取出之前的元素个数，队列空时返回 None
+    #[inline]
+    pub fn take(&self) -> Option<(T, usize)> {
+        if self.count.load(SeqCst) == 0 {
+            return None;
+        }
+        let start = self.start.load(Relaxed);
+        // SAFETY: count > 0，data[start] 已由生产者发布，在 count 减少之前生产者不会覆盖它
+        let item = self.data[start].with(|slot| unsafe { *slot });
+        self.start.store((start + 1) % SIZE, Relaxed);
+        Some((item, self.count.fetch_sub(1, SeqCst)))
+    }
+
+    /// 只能由唯一的消费者调用，队列空时返回 None
+    #[inline]
+    pub fn pop(&self) -> Option<T> {
+        self.take().map(|(item, _)| item)
+    }
+
+    /// 当前元素个数，并发修改时只是一个近似值
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count.load(SeqCst)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 放在共享页中的自旋锁字，全零即为未上锁。
+/// 共享同一队列的各个地址空间、各个核上的生产者（或消费者）都在同一个锁字上互斥，
+/// 使它们在 SpscQueue 看来只是一个生产者（或消费者）。
+/// 内核不访问锁字：它在异步系统调用通道上是唯一的消费者与生产者，用户态一侧不能再有第二个
+#[repr(transparent)]
+pub struct LockWord(AtomicBool);
+
+impl LockWord {
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    /// 临界区只有一次元素拷贝，自旋等待即可
+    #[inline]
+    pub fn lock(&self) -> LockWordGuard<'_> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.0.load(Relaxed) {
+                spin_loop();
+            }
+        }
+    }
+
+    #[inline]
+    pub fn try_lock(&self) -> Option<LockWordGuard<'_>> {
+        self.0.compare_exchange_weak(false, true, Acquire, Relaxed).ok().map(|_| LockWordGuard(self))
+    }
+}
+
+impl Default for LockWord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct LockWordGuard<'a>(&'a LockWord);
+
+impl Drop for LockWordGuard<'_> {
+    fn drop(&mut self) {
+        self.0 .0.store(false, Release);
+    }
+}
@@ -0,0 +1,287 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use crate::platform::get_clock;
+use crate::coroutine::CoroutineId;
+use crate::executor::MAX_PRIO_NUM;
+use crate::utils::{BitMap, BitMap64};
+
+/// 调度策略：管理就绪协程并决定下一个运行的协程。
+/// 优先级数值越小越优先。
+pub trait SchedPolicy {
+    /// 协程进入就绪状态
+    fn push(&mut self, cid: CoroutineId, prio: usize);
+
+    /// 取出下一个要运行的协程
+    fn pop(&mut self) -> Option<CoroutineId>;
+
+    /// 从就绪队列中删除协程，prio 为协程入队时的优先级。
+    /// 内置的策略都需要线性查找，只在修改优先级时使用；取消协程时不删除，由执行器跳过过期的 cid
+    fn remove(&mut self, cid: &CoroutineId, prio: usize) -> bool;
+
+    /// 是否有就绪协程应当抢占优先级为 prio 的当前协程
+    fn preempt_possible(&self, prio: usize) -> bool;
+
+    /// 优先级为 prio 的就绪协程数量
+    fn ready_num(&self, prio: usize) -> usize;
+
+    /// pop 取出的协程离开 CPU（结束、阻塞或让出）
+    fn leave(&mut self) {}
+}
+
+/// 按优先级分级的 FIFO 就绪队列
+pub struct PrioQueues {
+    ready_queue: [VecDeque<CoroutineId>; MAX_PRIO_NUM],
+    prio_bitmap: BitMap64,
+}
+
+impl PrioQueues {
+    pub fn new() -> Self {
+        Self {
+            ready_queue: core::array::from_fn(|_| VecDeque::new()),
+            prio_bitmap: BitMap64::new(),
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, cid: CoroutineId, prio: usize) {
+        self.prio_bitmap.set(prio);
+        self.ready_queue[prio].push_back(cid);
+    }
+
+    #[inline]
+    pub fn pop_prio(&mut self, prio: usize) -> Option<CoroutineId> {
+        let cid = self.ready_queue[prio].pop_front();
+        if self.ready_queue[prio].is_empty() {
+            self.prio_bitmap.clear(prio);
+        }
+        cid
+    }
+
+    pub fn remove(&mut self, cid: &CoroutineId, prio: usize) -> bool {
+        let queue = &mut self.ready_queue[prio];
+        let found = match queue.iter().position(|queued| queued == cid) {
+            Some(pos) => {
+                queue.remove(pos);
+                true
+            }
+            None => false,
+        };
+        if queue.is_empty() {
+            self.prio_bitmap.clear(prio);
+        }
+        found
+    }
+
+    /// 最高的非空优先级，全部为空时返回 64
+    #[inline]
+    pub fn highest(&self) -> usize {
+        self.prio_bitmap.find_first_one()
+    }
+
+    #[inline]
+    pub fn is_empty_prio(&self, prio: usize) -> bool {
+        !self.prio_bitmap.get(prio)
+    }
+
+    #[inline]
+    pub fn size(&self, prio: usize) -> usize {
+        self.ready_queue[prio].len()
+    }
+}
+
+impl Default for PrioQueues {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 严格固定优先级，同一优先级内 FIFO
+pub struct FixedPrioPolicy {
+    queues: PrioQueues,
+}
+
+impl FixedPrioPolicy {
+    pub fn new() -> Self {
+        Self { queues: PrioQueues::new() }
+    }
+}
+
+impl Default for FixedPrioPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedPolicy for FixedPrioPolicy {
+    #[inline]
+    fn push(&mut self, cid: CoroutineId, prio: usize) {
+        self.queues.push(cid, prio);
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<CoroutineId> {
+        let prio = self.queues.highest();
+        if prio == 64 {
+            return None;
+        }
+        self.queues.pop_prio(prio)
+    }
+
+    #[inline]
+    fn remove(&mut self, cid: &CoroutineId, prio: usize) -> bool {
+        self.queues.remove(cid, prio)
+    }
+
+    #[inline]
+    fn preempt_possible(&self, prio: usize) -> bool {
+        self.queues.highest() < prio
+    }
+
+    #[inline]
+    fn ready_num(&self, prio: usize) -> usize {
+        self.queues.size(prio)
+    }
+}
+
+/// 按权重在各优先级间轮转，每轮优先级 i 最多运行 weights[i] 个协程，避免低优先级饿死
+pub struct WeightedRoundRobinPolicy {
+    queues: PrioQueues,
+    weights: [usize; MAX_PRIO_NUM],
+    credits: [usize; MAX_PRIO_NUM],
+}
+
+impl WeightedRoundRobinPolicy {
+    /// 默认权重：优先级每降低一级权重减半
+    pub fn new() -> Self {
+        Self::with_weights(core::array::from_fn(|prio| 1 << (MAX_PRIO_NUM - 1 - prio)))
+    }
+
+    pub fn with_weights(weights: [usize; MAX_PRIO_NUM]) -> Self {
+        assert!(weights.iter().all(|&w| w > 0), "weight must be positive");
+        Self {
+            queues: PrioQueues::new(),
+            weights,
+            credits: weights,
+        }
+    }
+
+    /// 还有剩余额度的最高非空优先级
+    fn next_prio(&self) -> Option<usize> {
+        (0..MAX_PRIO_NUM).find(|&prio| !self.queues.is_empty_prio(prio) && self.credits[prio] > 0)
+    }
+}
+
+impl Default for WeightedRoundRobinPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedPolicy for WeightedRoundRobinPolicy {
+    #[inline]
+    fn push(&mut self, cid: CoroutineId, prio: usize) {
+        self.queues.push(cid, prio);
+    }
+
+    fn pop(&mut self) -> Option<CoroutineId> {
+        if self.queues.highest() == 64 {
+            return None;
+        }
+        let prio = match self.next_prio() {
+            Some(prio) => prio,
+            None => {
+                // 所有非空优先级的额度都已用完，开始新的一轮
+                self.credits = self.weights;
+                self.next_prio().unwrap()
+            }
+        };
+        self.credits[prio] -= 1;
+        self.queues.pop_prio(prio)
+    }
+
+    #[inline]
+    fn remove(&mut self, cid: &CoroutineId, prio: usize) -> bool {
+        self.queues.remove(cid, prio)
+    }
+
+    #[inline]
+    fn preempt_possible(&self, prio: usize) -> bool {
+        matches!(self.next_prio(), Some(next) if next < prio)
+    }
+
+    #[inline]
+    fn ready_num(&self, prio: usize) -> usize {
+        self.queues.size(prio)
+    }
+}
+
+/// 时钟周期，优先级为 0 的协程的默认相对截止时间
+pub const DEFAULT_EDF_SLICE: u64 = 10000;
+
+/// 最早截止时间优先：协程就绪时的截止时间为 get_clock() 加上其优先级对应的相对截止时间
+pub struct EarliestDeadlineFirstPolicy {
+    relative_deadlines: [u64; MAX_PRIO_NUM],
+    /// (截止时间, 序号) -> (协程, 优先级)
+    ready: BTreeMap<(u64, u64), (CoroutineId, usize)>,
+    seq: u64,
+    current_deadline: u64,
+}
+
+impl EarliestDeadlineFirstPolicy {
+    /// 默认优先级 i 的相对截止时间为 (i + 1) * DEFAULT_EDF_SLICE
+    pub fn new() -> Self {
+        Self::with_deadlines(core::array::from_fn(|prio| (prio as u64 + 1) * DEFAULT_EDF_SLICE))
+    }
+
+    pub fn with_deadlines(relative_deadlines: [u64; MAX_PRIO_NUM]) -> Self {
+        Self {
+            relative_deadlines,
+            ready: BTreeMap::new(),
+            seq: 0,
+            current_deadline: u64::MAX,
+        }
+    }
+}
+
+impl Default for EarliestDeadlineFirstPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedPolicy for EarliestDeadlineFirstPolicy {
+    fn push(&mut self, cid: CoroutineId, prio: usize) {
+        let deadline = get_clock() + self.relative_deadlines[prio];
+        self.ready.insert((deadline, self.seq), (cid, prio));
+        self.seq += 1;
+    }
+
+    fn pop(&mut self) -> Option<CoroutineId> {
+        let ((deadline, _), (cid, _)) = self.ready.pop_first()?;
+        self.current_deadline = deadline;
+        Some(cid)
+    }
+
+    fn remove(&mut self, cid: &CoroutineId, _prio: usize) -> bool {
+        let key = self.ready.iter().find(|(_, (ready_cid, _))| ready_cid == cid).map(|(key, _)| *key);
+        if let Some(key) = key {
+            self.ready.remove(&key);
+            return true;
+        }
+        false
+    }
+
+    #[inline]
+    fn preempt_possible(&self, _prio: usize) -> bool {
+        matches!(self.ready.first_key_value(), Some(((deadline, _), _)) if *deadline < self.current_deadline)
+    }
+
+    fn ready_num(&self, prio: usize) -> usize {
+        self.ready.values().filter(|(_, ready_prio)| *ready_prio == prio).count()
+    }
+
+    #[inline]
+    fn leave(&mut self) {
+        // 离开 CPU 后其截止时间不再参与抢占判断，再次就绪时按新的截止时间入队
+        self.current_deadline = u64::MAX;
+    }
+}
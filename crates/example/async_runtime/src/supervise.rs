@@ -0,0 +1,110 @@
+use alloc::boxed::Box;
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use crate::coroutine::CoroutineId;
+use crate::{get_executor, sleep};
+
+/// 可能失败的协程 future
+pub type FallibleFuture<E> = Pin<Box<dyn Future<Output=Result<(), E>> + 'static + Send + Sync>>;
+
+/// 协程返回 Err 时调用，参数为协程 cid 与错误
+pub type FailureHook = fn(&CoroutineId, &dyn Debug);
+
+/// 默认的失败处理：打印失败的协程与错误
+pub fn default_failure_hook(cid: &CoroutineId, err: &dyn Debug) {
+    debug_println!("coroutine {:?} failed: {:?}", cid, err);
+}
+
+/// 受监督协程失败后的重启策略
+#[derive(Clone, Copy, Debug)]
+pub enum RestartPolicy {
+    /// 失败后不再重启
+    Never,
+    /// 失败后等待 backoff 个时钟周期再重启，最多重启 max_restarts 次
+    OnFailure {
+        max_restarts: usize,
+        backoff: u64,
+    },
+    /// 失败后总是重启，两次重启之间至少间隔 ALWAYS_RESTART_BACKOFF 个时钟周期
+    Always,
+}
+
+/// RestartPolicy::Always 的重启间隔，避免持续失败的协程占满 CPU
+pub const ALWAYS_RESTART_BACKOFF: u64 = 10000;
+
+impl RestartPolicy {
+    /// 第 restarts 次失败后是否重启，以及重启前等待的时钟周期数
+    fn should_restart(&self, restarts: usize) -> Option<u64> {
+        match *self {
+            RestartPolicy::Never => None,
+            RestartPolicy::OnFailure { max_restarts, backoff } => {
+                if restarts < max_restarts {
+                    Some(backoff)
+                } else {
+                    None
+                }
+            }
+            RestartPolicy::Always => Some(ALWAYS_RESTART_BACKOFF),
+        }
+    }
+}
+
+/// 运行可能失败的 future，失败时以生成时分配的 cid 交给执行器的失败处理函数。
+/// 注意 no_std 环境下无法捕获 panic，panic 仍会终止整个线程
+pub(crate) fn wrap_fallible<E: Debug + 'static>(future: FallibleFuture<E>, cid: CoroutineId)
+    -> Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>> {
+    Box::pin(async move {
+        if let Err(err) = future.await {
+            get_executor().report_failure(&cid, &err);
+        }
+    })
+}
+
+/// 受监督的协程：factory 每次生成一个新的 future，失败后按 policy 在同一个协程（同一个 cid）中重启，
+/// 因此已登记的中断唤醒等 cid 关联状态在重启后依然有效
+pub(crate) fn wrap_supervised<E, F>(mut factory: F, policy: RestartPolicy, cid: CoroutineId)
+    -> Pin<Box<dyn Future<Output=()> + 'static + Send + Sync>>
+    where E: Debug + 'static, F: FnMut() -> FallibleFuture<E> + 'static + Send + Sync {
+    Box::pin(async move {
+        let mut restarts = 0;
+        loop {
+            if let Err(err) = factory().await {
+                get_executor().report_failure(&cid, &err);
+            } else {
+                return;
+            }
+            match policy.should_restart(restarts) {
+                Some(backoff) => {
+                    restarts += 1;
+                    if backoff != 0 {
+                        sleep(backoff).await;
+                    } else {
+                        // 不等待时也先让出一次，使同优先级的协程有机会运行
+                        YieldOnce { yielded: false }.await;
+                    }
+                }
+                None => return,
+            }
+        }
+    })
+}
+
+/// 让出一次 CPU：排到同优先级就绪队列的末尾后再继续
+struct YieldOnce {
+    yielded: bool,
+}
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
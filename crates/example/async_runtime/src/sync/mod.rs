@@ -0,0 +1,14 @@
+//! 与协程调度器集成的异步同步原语。
+//! 等待者通过 Waker 挂起并由执行器重新调度，不会自旋阻塞整个执行器。
+
+mod semaphore;
+mod mutex;
+mod rwlock;
+mod notify;
+pub mod oneshot;
+pub mod mpsc;
+
+pub use semaphore::*;
+pub use mutex::*;
+pub use rwlock::*;
+pub use notify::*;
@@ -0,0 +1,131 @@
+//! 有界多生产者单消费者通道，通道满时发送者挂起
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+use spin::Mutex;
+use super::Semaphore;
+
+struct ChanInner<T> {
+    queue: VecDeque<T>,
+    sender_num: usize,
+    waker: Option<Waker>,
+}
+
+struct Chan<T> {
+    /// 空闲槽位
+    slots: Semaphore,
+    inner: Mutex<ChanInner<T>>,
+}
+
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+/// 创建容量为 capacity 的通道
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be positive");
+    let chan = Arc::new(Chan {
+        slots: Semaphore::new(capacity),
+        inner: Mutex::new(ChanInner {
+            queue: VecDeque::with_capacity(capacity),
+            sender_num: 1,
+            waker: None,
+        }),
+    });
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+impl<T> Chan<T> {
+    fn push(&self, value: T) {
+        let mut inner = self.inner.lock();
+        inner.queue.push_back(value);
+        if let Some(waker) = inner.waker.take() {
+            drop(inner);
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// 发送值，通道满时等待；接收端已释放时原样返回
+    pub async fn send(&self, value: T) -> Result<(), T> {
+        if self.chan.slots.acquire(1).await.is_err() {
+            return Err(value);
+        }
+        self.chan.push(value);
+        Ok(())
+    }
+
+    /// 不等待地发送，通道满或接收端已释放时原样返回
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        if !self.chan.slots.try_acquire(1) {
+            return Err(value);
+        }
+        self.chan.push(value);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.slots.is_closed()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.inner.lock().sender_num += 1;
+        Self { chan: self.chan.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.chan.inner.lock();
+        inner.sender_num -= 1;
+        if inner.sender_num == 0 {
+            if let Some(waker) = inner.waker.take() {
+                drop(inner);
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 接收值，所有发送者都已释放且通道为空时返回 None
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| {
+            let mut inner = self.chan.inner.lock();
+            if let Some(value) = inner.queue.pop_front() {
+                drop(inner);
+                self.chan.slots.release(1);
+                return Poll::Ready(Some(value));
+            }
+            if inner.sender_num == 0 {
+                return Poll::Ready(None);
+            }
+            inner.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }).await
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        let value = self.chan.inner.lock().queue.pop_front();
+        if value.is_some() {
+            self.chan.slots.release(1);
+        }
+        value
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // 关闭槽位信号量，等待中的发送者返回 Err
+        self.chan.slots.close();
+    }
+}
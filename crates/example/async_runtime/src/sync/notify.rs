@@ -0,0 +1,121 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+struct NotifyInner {
+    /// 没有等待者时 notify_one 留下的许可
+    permit: bool,
+    waiters: VecDeque<(u64, Waker)>,
+    /// 已被通知但尚未再次 poll 的等待者
+    notified: Vec<u64>,
+    next_id: u64,
+}
+
+/// 协程间的事件通知
+pub struct Notify {
+    inner: Mutex<NotifyInner>,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(NotifyInner {
+                permit: false,
+                waiters: VecDeque::new(),
+                notified: Vec::new(),
+                next_id: 0,
+            })
+        }
+    }
+
+    /// 等待通知
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            id: None,
+        }
+    }
+
+    /// 唤醒最早的一个等待者；没有等待者时保存一个许可给下一次 notified()
+    pub fn notify_one(&self) {
+        let mut inner = self.inner.lock();
+        if let Some((id, waker)) = inner.waiters.pop_front() {
+            inner.notified.push(id);
+            drop(inner);
+            waker.wake();
+        } else {
+            inner.permit = true;
+        }
+    }
+
+    /// 唤醒当前所有等待者，不保存许可
+    pub fn notify_waiters(&self) {
+        let mut inner = self.inner.lock();
+        let waiters: Vec<_> = inner.waiters.drain(..).collect();
+        for (id, _) in waiters.iter() {
+            inner.notified.push(*id);
+        }
+        drop(inner);
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    id: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let notify = self.notify;
+        let mut inner = notify.inner.lock();
+        match self.id {
+            None => {
+                if inner.permit {
+                    inner.permit = false;
+                    return Poll::Ready(());
+                }
+                let id = inner.next_id;
+                inner.next_id += 1;
+                inner.waiters.push_back((id, cx.waker().clone()));
+                drop(inner);
+                self.id = Some(id);
+            }
+            Some(id) => {
+                if let Some(pos) = inner.notified.iter().position(|notified_id| *notified_id == id) {
+                    inner.notified.swap_remove(pos);
+                    drop(inner);
+                    self.id = None;
+                    return Poll::Ready(());
+                }
+                if let Some(waiter) = inner.waiters.iter_mut().find(|(waiter_id, _)| *waiter_id == id) {
+                    waiter.1 = cx.waker().clone();
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let mut inner = self.notify.inner.lock();
+            if let Some(pos) = inner.notified.iter().position(|notified_id| *notified_id == id) {
+                // 收到通知后被取消，把通知转交给下一个等待者
+                inner.notified.swap_remove(pos);
+                drop(inner);
+                self.notify.notify_one();
+            } else {
+                inner.waiters.retain(|(waiter_id, _)| *waiter_id != id);
+            }
+        }
+    }
+}
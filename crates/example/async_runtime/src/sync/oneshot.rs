@@ -0,0 +1,96 @@
+//! 单次发送的通道
+
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+struct Inner<T> {
+    value: Option<T>,
+    sender_closed: bool,
+    receiver_closed: bool,
+    waker: Option<Waker>,
+}
+
+pub struct Sender<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+pub struct Receiver<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Mutex::new(Inner {
+        value: None,
+        sender_closed: false,
+        receiver_closed: false,
+        waker: None,
+    }));
+    (Sender { inner: inner.clone() }, Receiver { inner })
+}
+
+impl<T> Sender<T> {
+    /// 发送值，接收端已释放时原样返回
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut inner = self.inner.lock();
+        if inner.receiver_closed {
+            return Err(value);
+        }
+        inner.value = Some(value);
+        Ok(())
+        // 唤醒接收端由 Drop 完成
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().receiver_closed
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.sender_closed = true;
+        if let Some(waker) = inner.waker.take() {
+            drop(inner);
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// 不等待地接收，尚未发送时返回 None，发送端未发送就被释放时返回 Some(Err(()))
+    pub fn try_recv(&mut self) -> Option<Result<T, ()>> {
+        let mut inner = self.inner.lock();
+        if let Some(value) = inner.value.take() {
+            return Some(Ok(value));
+        }
+        if inner.sender_closed {
+            return Some(Err(()));
+        }
+        None
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, ()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = self.inner.lock();
+        if let Some(value) = inner.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if inner.sender_closed {
+            return Poll::Ready(Err(()));
+        }
+        inner.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.lock().receiver_closed = true;
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::collections::VecDeque;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+struct SemaphoreInner {
+    permits: usize,
+    closed: bool,
+    /// FIFO 等待队列：(等待者编号, 需要的许可数, waker)
+    waiters: VecDeque<(u64, usize, Waker)>,
+    next_id: u64,
+}
+
+impl SemaphoreInner {
+    /// 队首等待者的许可已足够时唤醒它
+    fn wake_front(&mut self) {
+        if let Some((_, num, waker)) = self.waiters.front() {
+            if *num <= self.permits || self.closed {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+/// 异步信号量，按 FIFO 顺序分配许可
+pub struct Semaphore {
+    inner: Mutex<SemaphoreInner>,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            inner: Mutex::new(SemaphoreInner {
+                permits,
+                closed: false,
+                waiters: VecDeque::new(),
+                next_id: 0,
+            })
+        }
+    }
+
+    #[inline]
+    pub fn available_permits(&self) -> usize {
+        self.inner.lock().permits
+    }
+
+    /// 获取 num 个许可，信号量被关闭时返回 Err(())
+    pub fn acquire(&self, num: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            num,
+            id: None,
+        }
+    }
+
+    /// 不等待地获取 num 个许可
+    pub fn try_acquire(&self, num: usize) -> bool {
+        let mut inner = self.inner.lock();
+        if !inner.closed && inner.waiters.is_empty() && inner.permits >= num {
+            inner.permits -= num;
+            return true;
+        }
+        false
+    }
+
+    /// 归还 num 个许可
+    pub fn release(&self, num: usize) {
+        let mut inner = self.inner.lock();
+        inner.permits += num;
+        inner.wake_front();
+    }
+
+    /// 关闭信号量，所有等待者返回 Err(())
+    pub fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        for (_, _, waker) in inner.waiters.iter() {
+            waker.wake_by_ref();
+        }
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().closed
+    }
+}
+
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    num: usize,
+    /// 已进入等待队列时的编号
+    id: Option<u64>,
+}
+
+impl Future for Acquire<'_> {
+    type Output = Result<(), ()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        let mut inner = semaphore.inner.lock();
+        if inner.closed {
+            if let Some(id) = self.id.take() {
+                inner.waiters.retain(|(waiter_id, _, _)| *waiter_id != id);
+            }
+            return Poll::Ready(Err(()));
+        }
+        let is_front = match self.id {
+            Some(id) => matches!(inner.waiters.front(), Some((front_id, _, _)) if *front_id == id),
+            None => inner.waiters.is_empty(),
+        };
+        if is_front && inner.permits >= self.num {
+            inner.permits -= self.num;
+            if self.id.take().is_some() {
+                inner.waiters.pop_front();
+            }
+            // 剩余的许可可能还够下一个等待者
+            inner.wake_front();
+            return Poll::Ready(Ok(()));
+        }
+        match self.id {
+            Some(id) => {
+                if let Some(waiter) = inner.waiters.iter_mut().find(|(waiter_id, _, _)| *waiter_id == id) {
+                    waiter.2 = cx.waker().clone();
+                }
+            }
+            None => {
+                let id = inner.next_id;
+                inner.next_id += 1;
+                let num = self.num;
+                inner.waiters.push_back((id, num, cx.waker().clone()));
+                drop(inner);
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        // 等待中被取消：离开等待队列，并让出队首位置
+        if let Some(id) = self.id.take() {
+            let mut inner = self.semaphore.inner.lock();
+            inner.waiters.retain(|(waiter_id, _, _)| *waiter_id != id);
+            inner.wake_front();
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::Poll;
+use crate::coroutine::CoroutineId;
+use crate::join_handle::JoinHandle;
+use crate::{coroutine_spawn_joinable_with_prio, coroutine_current_prio, DEFAULT_PRIO};
+
+/// 协程组：组内子协程一起等待或一起取消，组被释放时仍未结束的子协程会被取消
+pub struct TaskGroup<T> {
+    children: Vec<Option<JoinHandle<T>>>,
+    remaining: usize,
+    prio: usize,
+}
+
+impl<T: Send + 'static> TaskGroup<T> {
+    /// 子协程继承当前协程的优先级，不在协程中时使用默认优先级
+    pub fn new() -> Self {
+        Self::with_prio(coroutine_current_prio().unwrap_or(DEFAULT_PRIO))
+    }
+
+    pub fn with_prio(prio: usize) -> Self {
+        Self {
+            children: Vec::new(),
+            remaining: 0,
+            prio,
+        }
+    }
+
+    /// 在组内创建子协程
+    pub fn spawn(&mut self, future: Pin<Box<dyn Future<Output=T> + 'static + Send + Sync>>) -> CoroutineId {
+        let handle = coroutine_spawn_joinable_with_prio(future, self.prio);
+        let cid = handle.cid();
+        self.children.push(Some(handle));
+        self.remaining += 1;
+        cid
+    }
+
+    /// 等待任意一个子协程结束，返回其在组内的序号与结果
+    async fn join_next_indexed(&mut self) -> Option<(usize, CoroutineId, Result<T, ()>)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (index, res) = poll_fn(|cx| {
+            for (index, child) in self.children.iter_mut().enumerate() {
+                if let Some(handle) = child {
+                    if let Poll::Ready(res) = Pin::new(handle).poll(cx) {
+                        return Poll::Ready((index, res));
+                    }
+                }
+            }
+            Poll::Pending
+        }).await;
+        let cid = self.children[index].take().unwrap().cid();
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.children.clear();
+        }
+        Some((index, cid, res))
+    }
+
+    /// 等待任意一个子协程结束，子协程被取消时结果为 Err(())；组为空时返回 None
+    pub async fn join_next(&mut self) -> Option<(CoroutineId, Result<T, ()>)> {
+        self.join_next_indexed().await
+            .map(|(_, cid, res)| (cid, res))
+    }
+
+    /// 按创建顺序返回尚未被等待的子协程的结果。
+    /// 任一子协程失败时取消其余子协程，并返回第一个失败的 cid
+    pub async fn join_all(&mut self) -> Result<Vec<T>, CoroutineId> {
+        let mut outputs: Vec<Option<T>> = (0..self.children.len()).map(|_| None).collect();
+        while let Some((index, cid, res)) = self.join_next_indexed().await {
+            match res {
+                Ok(output) => outputs[index] = Some(output),
+                Err(()) => {
+                    self.abort_all();
+                    self.children.clear();
+                    self.remaining = 0;
+                    return Err(cid);
+                }
+            }
+        }
+        Ok(outputs.into_iter().flatten().collect())
+    }
+}
+
+impl<T: Send + 'static> Default for TaskGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TaskGroup<T> {
+    /// 尚未被等待的子协程数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// 取消所有尚未被等待的子协程
+    pub fn abort_all(&self) {
+        for handle in self.children.iter().flatten() {
+            handle.abort();
+        }
+    }
+}
+
+impl<T> Drop for TaskGroup<T> {
+    fn drop(&mut self) {
+        self.abort_all();
+    }
+}
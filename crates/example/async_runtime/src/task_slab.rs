@@ -0,0 +1,110 @@
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::coroutine::{Coroutine, CoroutineId, CID_GENERATION_MASK, MAX_CID_NUM};
+
+/// 空闲槽位超过已用槽位的倍数时释放多余容量
+const SHRINK_RATIO: usize = 4;
+const MIN_CAPACITY: usize = 64;
+
+struct Slot {
+    generation: u32,
+    task: Option<Arc<Coroutine>>,
+}
+
+/// 以 cid 槽位下标存放协程的 slab。
+/// 槽位按需增长；末尾的空闲槽位会被截断并释放内存，最多容纳 MAX_CID_NUM 个协程。
+pub(crate) struct TaskSlab {
+    slots: Vec<Slot>,
+    /// 末尾之前的空闲槽位，总是优先复用下标最小的槽位，使末尾尽量空闲
+    free: BTreeSet<usize>,
+    len: usize,
+    /// 被截断的槽位中最大的下一代数，新建槽位从这里开始计数，避免过期 cid 与新协程重合
+    generation_floor: u32,
+}
+
+impl TaskSlab {
+    pub const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: BTreeSet::new(),
+            len: 0,
+            generation_floor: 0,
+        }
+    }
+
+    /// 存活的协程数
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 分配槽位并以分配到的 cid 创建协程，槽位用尽时返回 None
+    pub fn insert(&mut self, f: impl FnOnce(CoroutineId) -> Arc<Coroutine>) -> Option<CoroutineId> {
+        let index = match self.free.pop_first() {
+            Some(index) => index,
+            None => {
+                if self.slots.len() >= MAX_CID_NUM {
+                    return None;
+                }
+                self.slots.push(Slot {
+                    generation: self.generation_floor,
+                    task: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+        let slot = &mut self.slots[index];
+        let cid = CoroutineId::from_parts(index, slot.generation);
+        slot.task = Some(f(cid));
+        self.len += 1;
+        Some(cid)
+    }
+
+    /// 获取 cid 对应的协程，槽位已被回收或复用时返回 None
+    #[inline]
+    pub fn get(&self, cid: &CoroutineId) -> Option<&Arc<Coroutine>> {
+        match self.slots.get(cid.index()) {
+            Some(Slot { generation, task: Some(task) }) if *generation == cid.generation() => Some(task),
+            _ => None,
+        }
+    }
+
+    /// 移除协程并回收槽位，槽位代数加一使旧 cid 失效
+    pub fn remove(&mut self, cid: &CoroutineId) -> Option<Arc<Coroutine>> {
+        self.get(cid)?;
+        let index = cid.index();
+        let slot = &mut self.slots[index];
+        let task = slot.task.take();
+        slot.generation = (slot.generation + 1) & CID_GENERATION_MASK;
+        self.free.insert(index);
+        self.len -= 1;
+        self.shrink();
+        task
+    }
+
+    /// 截断末尾的空闲槽位
+    fn shrink(&mut self) {
+        while let Some(slot) = self.slots.last() {
+            if slot.task.is_some() {
+                break;
+            }
+            self.generation_floor = self.generation_floor.max(slot.generation);
+            self.free.remove(&(self.slots.len() - 1));
+            self.slots.pop();
+        }
+        let capacity = self.slots.capacity();
+        if capacity > MIN_CAPACITY && capacity > self.slots.len() * SHRINK_RATIO {
+            self.slots.shrink_to((self.slots.len() * 2).max(MIN_CAPACITY));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Arc<Coroutine>> {
+        self.slots.iter().filter_map(|slot| slot.task.as_ref())
+    }
+}
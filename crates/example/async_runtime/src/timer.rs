@@ -0,0 +1,172 @@
+use alloc::collections::BTreeMap;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use crate::platform::get_clock;
+use crate::{get_executor, get_executor_ptr};
+
+/// 定时器键：(截止时间, 序号)，序号用于区分同一截止时间的多个定时器
+pub type TimerKey = (u64, u64);
+
+pub struct TimerQueue {
+    timers: BTreeMap<TimerKey, Waker>,
+    seq: u64,
+}
+
+impl TimerQueue {
+    pub const fn new() -> Self {
+        Self {
+            timers: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+
+    #[inline]
+    pub fn add(&mut self, deadline: u64, waker: Waker) -> TimerKey {
+        let key = (deadline, self.seq);
+        self.seq += 1;
+        self.timers.insert(key, waker);
+        key
+    }
+
+    /// 更新尚未到期的定时器的 waker，定时器已到期或已删除时返回 false
+    #[inline]
+    pub fn update(&mut self, key: &TimerKey, waker: &Waker) -> bool {
+        match self.timers.get_mut(key) {
+            Some(old) => {
+                if !old.will_wake(waker) {
+                    *old = waker.clone();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, key: &TimerKey) {
+        self.timers.remove(key);
+    }
+
+    /// 取出一个在 now 之前到期的定时器
+    #[inline]
+    pub fn pop_expired(&mut self, now: u64) -> Option<Waker> {
+        let (&key, _) = self.timers.first_key_value()?;
+        if key.0 > now {
+            return None;
+        }
+        self.timers.remove(&key)
+    }
+
+    #[inline]
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.timers.first_key_value().map(|(key, _)| key.0)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+}
+
+pub struct Sleep {
+    deadline: u64,
+    /// 已登记的定时器及其所在执行器的地址
+    key: Option<(TimerKey, usize)>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// 删除已登记的定时器。共享协程可能已迁移到其他执行器，
+    /// 此时无法访问原执行器的定时器队列，留下的定时器到期后只会产生一次多余的唤醒
+    fn cancel(&mut self) {
+        if let Some((key, executor)) = self.key.take() {
+            if executor == get_executor_ptr() {
+                get_executor().timers.remove(&key);
+            }
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if get_clock() >= self.deadline {
+            self.cancel();
+            return Poll::Ready(());
+        }
+        let executor = get_executor_ptr();
+        match self.key {
+            Some((key, registered)) if registered == executor && get_executor().timers.update(&key, cx.waker()) => {}
+            _ => {
+                self.cancel();
+                let key = get_executor().timers.add(self.deadline, cx.waker().clone());
+                self.key = Some((key, executor));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // 提前释放的定时器不能再唤醒协程
+        self.cancel();
+    }
+}
+
+/// 睡眠直到时钟到达 deadline
+#[inline]
+pub fn sleep_until(deadline: u64) -> Sleep {
+    Sleep {
+        deadline,
+        key: None,
+    }
+}
+
+/// 睡眠 ticks 个时钟周期
+#[inline]
+pub fn sleep(ticks: u64) -> Sleep {
+    sleep_until(get_clock() + ticks)
+}
+
+pub struct Timeout<F: Future> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, ()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: future and sleep are never moved out of the pinned Timeout
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(_) => Poll::Ready(Err(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// 为 future 设置 ticks 个时钟周期的超时，超时后返回 Err(())
+#[inline]
+pub fn timeout<F: Future>(future: F, ticks: u64) -> Timeout<F> {
+    timeout_at(future, get_clock() + ticks)
+}
+
+/// 时钟到达 deadline 时 future 仍未完成则返回 Err(())，超时后 future 随 Timeout 一同释放
+#[inline]
+pub fn timeout_at<F: Future>(future: F, deadline: u64) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep_until(deadline),
+    }
+}
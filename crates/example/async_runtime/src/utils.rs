@@ -1,31 +1,47 @@
 
-use core::sync::atomic::AtomicUsize;
-use sel4::debug_println;
-use sel4::get_clock;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering::{AcqRel, Acquire, Release};
 
 #[derive(Copy, Clone)]
 pub struct IndexAllocator<const SIZE: usize> where
-    [(); (SIZE + 7) / 8]: {
-    bitmap: [u8; (SIZE + 7) / 8]
+    [(); (SIZE + 63) / 64]: {
+    bitmap: [u64; (SIZE + 63) / 64],
+    /// 下一次分配开始查找的字
+    hint: usize,
 }
 
 impl<const SIZE: usize> IndexAllocator<SIZE> where
-    [(); (SIZE + 7) / 8]: {
+    [(); (SIZE + 63) / 64]: {
+    const WORDS: usize = (SIZE + 63) / 64;
+
     pub const fn new() -> Self {
         Self {
-            bitmap :[0; (SIZE + 7) / 8]
+            bitmap: [0; (SIZE + 63) / 64],
+            hint: 0,
         }
     }
 
     pub fn allocate(&mut self) -> Option<usize> {
-        (0..SIZE).find(|i| {self.bitmap[i / 8] & (1 << (i % 8)) == 0 }).map(|index| {
-            self.bitmap[index / 8] |= 1 << (index % 8);
-            index
-        })
+        for i in 0..Self::WORDS {
+            let word_index = (self.hint + i) % Self::WORDS;
+            let word = self.bitmap[word_index];
+            if word == u64::MAX {
+                continue;
+            }
+            let bit = word.trailing_ones() as usize;
+            let index = (word_index << 6) + bit;
+            if index >= SIZE {
+                continue;
+            }
+            self.bitmap[word_index] |= 1 << bit;
+            self.hint = word_index;
+            return Some(index);
+        }
+        None
     }
 
     pub fn release(&mut self, index: usize) {
-        self.bitmap[index / 8] &= !(1 << (index % 8));
+        self.bitmap[index >> 6] &= !(1 << (index & 0b0011_1111));
     }
 }
 
@@ -101,7 +117,7 @@ impl BitMap for BitMap4096 {
             return 64 << 6;
         }
         let l2_index = self.l2[l1_index].find_first_one();
-        return (l1_index << 6) + l2_index;
+        (l1_index << 6) + l2_index
     }
 
     fn find_first_zero(&self) -> usize {
@@ -116,6 +132,58 @@ impl BitMap for BitMap4096 {
 }
 
 
+/// 可在中断上下文中并发置位的两级位图，由单一消费者批量取出。
+/// 一级位图的每一位对应一个非零的二级字
+pub struct AtomicBitMap<const SIZE: usize> where
+    [(); (SIZE + 63) / 64]:,
+    [(); (SIZE + 4095) / 4096]: {
+    l1: [AtomicU64; (SIZE + 4095) / 4096],
+    l2: [AtomicU64; (SIZE + 63) / 64],
+}
+
+impl<const SIZE: usize> AtomicBitMap<SIZE> where
+    [(); (SIZE + 63) / 64]:,
+    [(); (SIZE + 4095) / 4096]: {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            l1: [const { AtomicU64::new(0) }; (SIZE + 4095) / 4096],
+            l2: [const { AtomicU64::new(0) }; (SIZE + 63) / 64],
+        }
+    }
+
+    #[inline]
+    pub fn set(&self, pos: usize) {
+        assert!(pos < SIZE, "Position out of range");
+        let l2_index = pos >> 6;
+        // 先置二级位再置一级位，保证消费者看到一级位时二级位已可见
+        self.l2[l2_index].fetch_or(1 << (pos & 0b0011_1111), Release);
+        self.l1[l2_index >> 6].fetch_or(1 << (l2_index & 0b0011_1111), Release);
+    }
+
+    #[inline]
+    pub fn empty(&self) -> bool {
+        self.l1.iter().all(|word| word.load(Acquire) == 0)
+    }
+
+    /// 取出并清空所有已置位的位置，对每个位置调用 f
+    pub fn drain(&self, mut f: impl FnMut(usize)) {
+        for (l1_word, l1_entry) in self.l1.iter().enumerate() {
+            let mut l1 = l1_entry.swap(0, AcqRel);
+            while l1 != 0 {
+                let l2_index = (l1_word << 6) + l1.trailing_zeros() as usize;
+                l1 &= l1 - 1;
+                let mut l2 = self.l2[l2_index].swap(0, AcqRel);
+                while l2 != 0 {
+                    let bit = l2.trailing_zeros() as usize;
+                    l2 &= l2 - 1;
+                    f((l2_index << 6) + bit);
+                }
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct BitMap64 {
     pub data: u64,
@@ -196,6 +264,7 @@ impl<T, const SIZE: usize> RingBuffer<T, SIZE> where T: Default + Copy + Clone {
     }
 
     #[inline]
+    #[allow(clippy::result_unit_err)]
     pub fn push(&mut self, item: &T) -> Result<(), ()> {
         if !self.full() {
             self.data[self.end] = *item;
@@ -207,7 +276,7 @@ impl<T, const SIZE: usize> RingBuffer<T, SIZE> where T: Default + Copy + Clone {
 
     #[inline]
     pub fn pop(&mut self) -> Option<T> {
-        return if !self.empty() {
+        if !self.empty() {
             let ans = self.data[self.start];
             self.start = (self.start + 1) % SIZE;
             Some(ans)
@@ -215,49 +284,26 @@ impl<T, const SIZE: usize> RingBuffer<T, SIZE> where T: Default + Copy + Clone {
             None
         }
     }
-}
-
-pub struct SafeRingBuffer<T, const SIZE: usize> {
-    data: [T; SIZE],
-    pub start: usize,
-    pub end: usize,
-    pub count: AtomicUsize,
-}
-
-impl<T, const SIZE: usize> SafeRingBuffer<T, SIZE> where T: Default + Copy + Clone {
-    pub fn new() -> Self {
-        Self {
-            data: [T::default(); SIZE],
-            start: 0,
-            end: 0,
-            count: AtomicUsize::new(0)
-        }
-    }
 
-    #[inline]
-    pub fn pop_safe(&mut self) -> Option<T> {
-        if !(self.count.load(core::sync::atomic::Ordering::Relaxed) == 0) {
-            let item = self.data[self.start];
-            self.start = (self.start + 1) % SIZE;
-            self.count.fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
-            Some(item)
-        } else {
-            None
+    /// 删除第一个与 item 相等的元素，保持其余元素的顺序。
+    /// 需要把所有元素出队再入队一遍，复杂度为 O(n)，不要在热路径上使用
+    pub fn remove(&mut self, item: &T) -> bool where T: PartialEq {
+        let size = self.size();
+        let mut found = false;
+        for _ in 0..size {
+            let cur = self.pop().unwrap();
+            if !found && cur == *item {
+                found = true;
+                continue;
+            }
+            self.push(&cur).unwrap();
         }
+        found
     }
+}
 
-    #[inline]
-    pub fn push_safe(&mut self, item: &T) -> Result<(), ()> {
-        let cnt = self.count.load(core::sync::atomic::Ordering::Relaxed);
-        // debug_println!("push cnt: {}, SIZE: {}", cnt, SIZE);
-        if !(self.count.load(core::sync::atomic::Ordering::SeqCst) == SIZE) {
-            // debug_println!("push cnt: {}, SIZE: {}", cnt, SIZE);
-            self.data[self.end] = *item;
-            self.end = (self.end + 1) % SIZE;
-            self.count.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
-            Ok(())
-        } else {
-            Err(())
-        }
+impl<T, const SIZE: usize> Default for RingBuffer<T, SIZE> where T: Default + Copy + Clone {
+    fn default() -> Self {
+        Self::new()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,60 @@
+//! 宿主机测试共用的辅助函数
+#![allow(dead_code)]
+
+use std::future::{poll_fn, Future};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use async_runtime::{coroutine_spawn, NewBuffer};
+
+/// 唤醒自己并让出一次
+pub async fn yield_once() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }).await
+}
+
+/// 协程写入、测试线程在执行器跑完后取出的结果
+pub struct Output<T>(Arc<Mutex<Option<T>>>);
+
+impl<T> Output<T> {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(None)))
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.lock().unwrap() = Some(value);
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl<T> Clone for Output<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// 以默认优先级创建协程，其返回值写入 Output
+pub fn spawn_with_output<T, F>(future: F) -> Output<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + Sync + 'static,
+{
+    let output = Output::new();
+    let slot = output.clone();
+    coroutine_spawn(Box::pin(async move { slot.set(future.await) }));
+    output
+}
+
+/// 与 root task 一样用全零的页分配 NewBuffer
+pub fn leak_new_buffer() -> &'static NewBuffer {
+    unsafe { &*(std::alloc::alloc_zeroed(std::alloc::Layout::new::<NewBuffer>()) as *const NewBuffer) }
+}
@@ -0,0 +1,441 @@
+//! 宿主机上的执行器测试：cargo test --no-default-features --features std
+
+mod common;
+
+use std::future::pending;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+use async_runtime::*;
+use common::{spawn_with_output, yield_once};
+
+fn record(log: &Arc<Mutex<Vec<usize>>>, value: usize) {
+    log.lock().unwrap().push(value);
+}
+
+#[test]
+fn runs_by_priority() {
+    runtime_init();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for prio in [3, 1, 2, 0] {
+        let log = log.clone();
+        coroutine_spawn_with_prio(Box::pin(async move { record(&log, prio) }), prio);
+    }
+    coroutine_run_until_complete();
+    assert_eq!(*log.lock().unwrap(), vec![0, 1, 2, 3]);
+    assert!(coroutine_is_empty());
+}
+
+#[test]
+fn same_priority_is_fifo() {
+    runtime_init();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..3 {
+        let log = log.clone();
+        coroutine_spawn(Box::pin(async move {
+            record(&log, i);
+            yield_once().await;
+            record(&log, i + 10);
+        }));
+    }
+    coroutine_run_until_complete();
+    assert_eq!(*log.lock().unwrap(), vec![0, 1, 2, 10, 11, 12]);
+}
+
+#[test]
+fn stale_cid_is_not_woken() {
+    runtime_init();
+    let cid = coroutine_spawn(Box::pin(async {}));
+    coroutine_run_until_complete();
+    assert!(!coroutine_is_alive(&cid));
+    assert!(!coroutine_wake(&cid));
+    assert_eq!(coroutine_stale_wake_num(), 1);
+    // 复用同一槽位的新协程不会被旧 cid 唤醒
+    let new_cid = coroutine_spawn(Box::pin(async {}));
+    assert_eq!(new_cid.index(), cid.index());
+    assert_ne!(new_cid, cid);
+    assert!(!coroutine_abort(&cid));
+    assert!(coroutine_is_alive(&new_cid));
+    coroutine_run_until_complete();
+}
+
+#[test]
+fn delay_wake_reaches_every_slot() {
+    runtime_init();
+    const NUM: usize = 3000;
+    let done = Arc::new(AtomicUsize::new(0));
+    let cids: Vec<_> = (0..NUM).map(|_| {
+        let done = done.clone();
+        coroutine_spawn(Box::pin(async move {
+            pending_once().await;
+            done.fetch_add(1, SeqCst);
+        }))
+    }).collect();
+    coroutine_run_until_blocked();
+    // 与 uintr handler 一样只在位图中登记，下一次 fetch 时批量唤醒
+    for cid in cids.iter().rev() {
+        coroutine_delay_wake(cid);
+    }
+    coroutine_run_until_complete();
+    assert_eq!(done.load(SeqCst), NUM);
+}
+
+#[test]
+fn stale_delay_wake_does_not_hide_valid_one() {
+    runtime_init();
+    let old_cid = coroutine_spawn(Box::pin(async {}));
+    coroutine_run_until_complete();
+    let done = Arc::new(AtomicUsize::new(0));
+    let cid = {
+        let done = done.clone();
+        coroutine_spawn(Box::pin(async move {
+            pending_once().await;
+            done.fetch_add(1, SeqCst);
+        }))
+    };
+    assert_eq!(cid.index(), old_cid.index());
+    coroutine_run_until_blocked();
+    // 新协程的唤醒之后才到达的过期唤醒不能覆盖它
+    coroutine_delay_wake(&cid);
+    coroutine_delay_wake(&old_cid);
+    coroutine_run_until_complete();
+    assert_eq!(done.load(SeqCst), 1);
+    assert_eq!(coroutine_stale_wake_num(), 1);
+}
+
+/// 挂起一次，等待外部唤醒
+async fn pending_once() {
+    let mut polled = false;
+    std::future::poll_fn(|_| {
+        if polled {
+            return std::task::Poll::Ready(());
+        }
+        polled = true;
+        std::task::Poll::Pending
+    }).await
+}
+
+#[test]
+fn task_table_grows_beyond_old_limit() {
+    runtime_init();
+    const NUM: usize = 10000;
+    let done = Arc::new(AtomicUsize::new(0));
+    for _ in 0..NUM {
+        let done = done.clone();
+        coroutine_spawn(Box::pin(async move {
+            yield_once().await;
+            done.fetch_add(1, SeqCst);
+        }));
+    }
+    coroutine_run_until_complete();
+    assert_eq!(done.load(SeqCst), NUM);
+    assert!(coroutine_is_empty());
+}
+
+#[test]
+fn abort_releases_future() {
+    runtime_init();
+    let dropped = Arc::new(AtomicUsize::new(0));
+    struct DropCounter(Arc<AtomicUsize>);
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+    let counter = DropCounter(dropped.clone());
+    let cid = coroutine_spawn(Box::pin(async move {
+        let _counter = counter;
+        pending::<()>().await;
+    }));
+    coroutine_run_until_blocked();
+    assert!(coroutine_abort(&cid));
+    assert_eq!(dropped.load(SeqCst), 1);
+    assert!(coroutine_is_empty());
+}
+
+#[test]
+fn aborted_ready_coroutine_is_skipped() {
+    runtime_init();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log_clone = log.clone();
+    let aborted = coroutine_spawn(Box::pin(async move { record(&log_clone, 0) }));
+    assert!(coroutine_abort(&aborted));
+    // 新协程复用同一槽位，就绪队列中残留的旧 cid 不会让它多运行一次
+    let log_clone = log.clone();
+    let reused = coroutine_spawn(Box::pin(async move { record(&log_clone, 1) }));
+    assert_eq!(reused.index(), aborted.index());
+    coroutine_run_until_complete();
+    assert_eq!(*log.lock().unwrap(), vec![1]);
+}
+
+static ABORTED: Mutex<Vec<CoroutineId>> = Mutex::new(Vec::new());
+
+fn record_abort(cid: &CoroutineId) {
+    ABORTED.lock().unwrap().push(*cid);
+}
+
+#[test]
+fn abort_hook_sees_aborted_cid() {
+    runtime_init();
+    coroutine_set_abort_hook(record_abort);
+    let cid = coroutine_spawn(Box::pin(pending::<()>()));
+    coroutine_run_until_blocked();
+    assert!(coroutine_abort(&cid));
+    assert!(ABORTED.lock().unwrap().contains(&cid));
+}
+
+std::thread_local! {
+    static REMOVED: std::cell::RefCell<Vec<CoroutineId>> = std::cell::RefCell::new(Vec::new());
+}
+
+fn record_remove(cid: &CoroutineId) {
+    REMOVED.with(|removed| removed.borrow_mut().push(*cid));
+}
+
+#[test]
+fn remove_hook_sees_finished_and_aborted_cids() {
+    runtime_init();
+    coroutine_set_remove_hook(record_remove);
+    let finished = coroutine_spawn(Box::pin(async {}));
+    let aborted = coroutine_spawn(Box::pin(pending::<()>()));
+    coroutine_run_until_blocked();
+    assert!(coroutine_abort(&aborted));
+    REMOVED.with(|removed| {
+        let removed = removed.borrow();
+        assert!(removed.contains(&finished));
+        assert!(removed.contains(&aborted));
+    });
+}
+
+#[test]
+fn sleep_and_timeout() {
+    runtime_init();
+    let result = spawn_with_output(async {
+        let start = platform::get_clock();
+        sleep(1_000_000).await;
+        let slept = platform::get_clock() - start;
+        let timed_out = timeout(pending::<()>(), 1_000_000).await;
+        let in_time = timeout(async { 7 }, 1_000_000).await;
+        (slept >= 1_000_000, timed_out, in_time)
+    });
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some((true, Err(()), Ok(7))));
+}
+
+#[test]
+fn task_group_joins_and_reports_first_failure() {
+    runtime_init();
+    let result = spawn_with_output(async {
+        let mut group = TaskGroup::new();
+        for i in 0..4 {
+            group.spawn(Box::pin(async move {
+                yield_once().await;
+                i * 2
+            }));
+        }
+        let outputs = group.join_all().await;
+
+        let mut failing = TaskGroup::<usize>::new();
+        let blocked = failing.spawn(Box::pin(async { pending().await }));
+        failing.spawn(Box::pin(async { 1 }));
+        let (_, first) = failing.join_next().await.unwrap();
+        coroutine_abort(&blocked);
+        let failure = failing.join_all().await;
+        (outputs, first, failure == Err(blocked))
+    });
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some((Ok(vec![0, 2, 4, 6]), Ok(1), true)));
+}
+
+#[test]
+fn combinators() {
+    runtime_init();
+    let result = spawn_with_output(async {
+        let joined = join(async { 1 }, async {
+            yield_once().await;
+            2
+        }).await;
+        let all = join_all((0..5).map(|i| async move {
+            for _ in 0..i {
+                yield_once().await;
+            }
+            i
+        }).collect()).await;
+        let selected = select(pending::<()>(), sleep(1000)).await;
+        let raced = race(vec![Box::pin(sleep(10_000_000)), Box::pin(sleep(0))]).await.0;
+        (joined, all, selected == Either::Right(()), raced)
+    });
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some(((1, 2), vec![0, 1, 2, 3, 4], true, 1)));
+}
+
+#[test]
+fn consume_budget_interleaves_same_priority() {
+    runtime_init();
+    runtime_set_poll_budget(PollBudget { ops: 2, cycles: 0 });
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for id in 0..2 {
+        let log = log.clone();
+        coroutine_spawn(Box::pin(async move {
+            for _ in 0..4 {
+                record(&log, id);
+                consume_budget().await;
+            }
+        }));
+    }
+    coroutine_run_until_complete();
+    assert_eq!(*log.lock().unwrap(), vec![0, 0, 1, 1, 0, 0, 1, 1]);
+}
+
+#[test]
+fn budget_yield_is_not_counted_as_wake() {
+    runtime_init();
+    runtime_set_poll_budget(PollBudget { ops: 1, cycles: 0 });
+    let cid = coroutine_spawn(Box::pin(async {
+        consume_budget().await;
+        consume_budget().await;
+        pending::<()>().await;
+    }));
+    coroutine_run_until_blocked();
+    let stats = coroutine_stats(&cid).unwrap();
+    assert_eq!(stats.poll_num, 3);
+    assert_eq!(stats.wake_num, 0);
+    coroutine_abort(&cid);
+}
+
+static SUPERVISED_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+fn count_failure(_cid: &CoroutineId, _err: &dyn core::fmt::Debug) {
+    SUPERVISED_FAILURES.fetch_add(1, SeqCst);
+}
+
+#[test]
+fn supervised_coroutine_restarts_in_place() {
+    runtime_init();
+    coroutine_set_failure_hook(count_failure);
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let cids = Arc::new(Mutex::new(Vec::new()));
+    let (attempts_clone, cids_clone) = (attempts.clone(), cids.clone());
+    coroutine_spawn_supervised(move || {
+        let (attempts, cids) = (attempts_clone.clone(), cids_clone.clone());
+        Box::pin(async move {
+            cids.lock().unwrap().push(coroutine_get_current());
+            if attempts.fetch_add(1, SeqCst) < 2 {
+                return Err("not yet");
+            }
+            Ok(())
+        })
+    }, RestartPolicy::OnFailure { max_restarts: 5, backoff: 0 }, 1);
+    coroutine_spawn_fallible(Box::pin(async { Err::<(), _>(()) }), 1);
+    coroutine_run_until_complete();
+    assert_eq!(attempts.load(SeqCst), 3);
+    assert_eq!(SUPERVISED_FAILURES.load(SeqCst), 3);
+    let cids = cids.lock().unwrap();
+    assert!(cids.iter().all(|cid| *cid == cids[0]));
+}
+
+std::thread_local! {
+    static FAILED: std::cell::RefCell<Vec<CoroutineId>> = std::cell::RefCell::new(Vec::new());
+}
+
+fn record_failed(cid: &CoroutineId, _err: &dyn core::fmt::Debug) {
+    FAILED.with(|failed| failed.borrow_mut().push(*cid));
+}
+
+#[test]
+fn failure_reports_cid_assigned_at_spawn() {
+    runtime_init();
+    coroutine_set_failure_hook(record_failed);
+    let fallible = coroutine_spawn_fallible(Box::pin(async { Err::<(), _>(()) }), 1);
+    let supervised = coroutine_spawn_supervised(|| Box::pin(async { Err::<(), _>(()) }), RestartPolicy::Never, 1);
+    coroutine_run_until_complete();
+    FAILED.with(|failed| assert_eq!(*failed.borrow(), vec![fallible, supervised]));
+}
+
+#[test]
+fn always_restart_does_not_starve_other_coroutines() {
+    runtime_init();
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let attempts_clone = attempts.clone();
+    let supervised = coroutine_spawn_supervised(move || {
+        let attempts = attempts_clone.clone();
+        Box::pin(async move {
+            attempts.fetch_add(1, SeqCst);
+            Err(())
+        })
+    }, RestartPolicy::Always, 1);
+    let done = Arc::new(AtomicUsize::new(0));
+    let done_clone = done.clone();
+    coroutine_spawn(Box::pin(async move {
+        yield_once().await;
+        done_clone.store(1, SeqCst);
+    }));
+    // 每次重启前等待 ALWAYS_RESTART_BACKOFF，期间其他协程照常运行
+    let start = platform::get_clock();
+    coroutine_run_until_blocked();
+    let elapsed = platform::get_clock() - start;
+    assert_eq!(done.load(SeqCst), 1);
+    assert!(attempts.load(SeqCst) as u64 <= elapsed / ALWAYS_RESTART_BACKOFF + 1);
+    assert!(coroutine_abort(&supervised));
+}
+
+#[test]
+fn mailbox_delivers_in_order() {
+    runtime_init();
+    static MAILBOX: Mailbox<usize> = Mailbox::new(2);
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    let cid = coroutine_spawn(Box::pin(async move {
+        for _ in 0..3 {
+            let value = MAILBOX.recv().await;
+            received_clone.lock().unwrap().push(value);
+        }
+    }));
+    coroutine_run_until_blocked();
+    assert!(MAILBOX.send(&cid, 1).is_ok());
+    assert!(MAILBOX.send(&cid, 2).is_ok());
+    assert_eq!(MAILBOX.send(&cid, 3), Err(3));
+    coroutine_run_until_blocked();
+    assert!(MAILBOX.send(&cid, 3).is_ok());
+    coroutine_run_until_complete();
+    assert_eq!(*received.lock().unwrap(), vec![1, 2, 3]);
+    assert_eq!(MAILBOX.send(&cid, 4), Err(4));
+}
+
+#[test]
+fn spawn_local_accepts_non_send_future() {
+    runtime_init();
+    let shared = Rc::new(AtomicUsize::new(0));
+    let shared_clone = shared.clone();
+    coroutine_spawn_local(Box::pin(async move {
+        yield_once().await;
+        shared_clone.fetch_add(1, SeqCst);
+    }));
+    coroutine_run_until_complete();
+    assert_eq!(shared.load(SeqCst), 1);
+    // 不可跨线程的结果同样可以通过 JoinHandle 取回
+    let handle = coroutine_spawn_local_joinable_with_prio(Box::pin(async { Rc::new(2) }), 3);
+    let shared_clone = shared.clone();
+    coroutine_spawn_local(Box::pin(async move {
+        let value = handle.await.unwrap();
+        shared_clone.fetch_add(*value, SeqCst);
+    }));
+    coroutine_run_until_complete();
+    assert_eq!(shared.load(SeqCst), 3);
+}
+
+#[test]
+fn stats_count_polls_and_wakes() {
+    runtime_init();
+    let cid = coroutine_spawn(Box::pin(async {
+        yield_once().await;
+        yield_once().await;
+        pending::<()>().await;
+    }));
+    coroutine_run_until_blocked();
+    let stats = coroutine_stats(&cid).unwrap();
+    assert_eq!(stats.poll_num, 3);
+    assert_eq!(stats.wake_num, 2);
+    coroutine_abort(&cid);
+    assert!(coroutine_stats(&cid).is_none());
+}
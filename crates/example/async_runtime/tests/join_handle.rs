@@ -0,0 +1,46 @@
+//! 宿主机上的 JoinHandle 测试：cargo test --no-default-features --features std
+
+mod common;
+
+use std::future::pending;
+use async_runtime::*;
+use common::Output;
+
+#[test]
+fn join_handle_returns_output_or_err_on_abort() {
+    runtime_init();
+    let finished = coroutine_spawn_joinable(Box::pin(async { 42 }));
+    let aborted = coroutine_spawn_joinable(Box::pin(async {
+        pending::<()>().await;
+        0
+    }));
+    let result = Output::new();
+    let result_clone = result.clone();
+    coroutine_spawn_with_prio(Box::pin(async move {
+        let output = finished.await;
+        aborted.abort();
+        result_clone.set((output, aborted.await));
+    }), 0);
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some((Ok(42), Err(()))));
+}
+
+#[test]
+fn block_on_returns_output() {
+    runtime_init();
+    let output = block_on(Box::pin(async {
+        let handle = coroutine_spawn_joinable(Box::pin(async { 1 }));
+        handle.await.unwrap() + 1
+    }));
+    assert_eq!(output, 2);
+}
+
+#[test]
+fn abort_before_first_poll_resolves_handle() {
+    runtime_init();
+    // 守卫随协程一起创建，future 从未被 poll 也会在释放时通知句柄
+    let handle = coroutine_spawn_joinable(Box::pin(async { 1 }));
+    assert!(coroutine_abort(&handle.cid()));
+    assert_eq!(handle.try_take(), Some(Err(())));
+    assert!(handle.is_finished());
+}
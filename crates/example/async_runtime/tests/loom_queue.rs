@@ -0,0 +1,118 @@
+//! 共享队列的模型检查，遍历所有线程交错与内存序：
+//! RUSTFLAGS="--cfg loom" cargo test --release --no-default-features --features std --test loom_queue
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use async_runtime::queue::{LockWord, SpscQueue};
+
+/// 两个字互相校验的元素，读到写了一半的元素时校验失败
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct Item(usize, usize);
+
+impl Item {
+    fn new(value: usize) -> Self {
+        Self(value, !value)
+    }
+
+    fn value(&self) -> usize {
+        assert_eq!(self.1, !self.0, "garbled item");
+        self.0
+    }
+}
+
+/// 共享页中的队列加写者锁字，与 NewBuffer 中的 SharedItems 相同
+struct Shared {
+    queue: SpscQueue<Item, 2>,
+    write: LockWord,
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            queue: SpscQueue::new(),
+            write: LockWord::new(),
+        }
+    }
+
+    fn push(&self, value: usize) -> bool {
+        let _guard = self.write.lock();
+        self.queue.push(&Item::new(value)).is_ok()
+    }
+}
+
+#[test]
+fn spsc_publishes_items_in_order() {
+    loom::model(|| {
+        let queue = Arc::new(SpscQueue::<Item, 2>::new());
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for value in 1..=3 {
+                    while queue.push(&Item::new(value)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+        let mut expected = 1;
+        while expected <= 3 {
+            match queue.pop() {
+                Some(item) => {
+                    assert_eq!(item.value(), expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    });
+}
+
+#[test]
+fn locked_producers_share_one_queue() {
+    loom::model(|| {
+        let shared = Arc::new(Shared::new());
+        // 先推进一个位置，让并发写入跨过队列末尾回绕
+        assert!(shared.push(0));
+        assert_eq!(shared.queue.pop().map(|item| item.value()), Some(0));
+        let producers: Vec<_> = [10, 20].into_iter().map(|value| {
+            let shared = shared.clone();
+            thread::spawn(move || assert!(shared.push(value)))
+        }).collect();
+        // 与生产者并发弹出：可能为空，但不能读到未发布的元素
+        let mut received: Vec<_> = shared.queue.pop().map(|item| item.value()).into_iter().collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        while let Some(item) = shared.queue.pop() {
+            received.push(item.value());
+        }
+        received.sort();
+        assert_eq!(received, vec![10, 20]);
+    });
+}
+
+#[test]
+fn locked_full_queue_rejects_without_corruption() {
+    loom::model(|| {
+        let shared = Arc::new(Shared::new());
+        assert!(shared.push(1));
+        let producers: Vec<_> = [2, 3].into_iter().map(|value| {
+            let shared = shared.clone();
+            // 与消费者竞争时可能成功也可能因队列满失败，但不能破坏已有元素
+            thread::spawn(move || shared.push(value).then_some(value))
+        }).collect();
+        let first = shared.queue.pop().map(|item| item.value());
+        let mut pushed: Vec<_> = producers.into_iter().filter_map(|producer| producer.join().unwrap()).collect();
+        assert_eq!(first, Some(1));
+        let mut rest = Vec::new();
+        while let Some(item) = shared.queue.pop() {
+            rest.push(item.value());
+        }
+        assert!(!pushed.is_empty());
+        pushed.sort();
+        rest.sort();
+        assert_eq!(rest, pushed);
+    });
+}
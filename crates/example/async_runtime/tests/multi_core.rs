@@ -0,0 +1,221 @@
+//! 宿主机上的多执行器压力测试：cargo test --no-default-features --features std
+
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread;
+use async_runtime::platform::HostNotification;
+use async_runtime::*;
+
+/// 由其他线程置位并唤醒的事件
+struct Event {
+    set: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Event {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            set: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        })
+    }
+
+    fn set(&self) {
+        self.set.store(true, SeqCst);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    async fn wait(&self) {
+        poll_fn(|cx| {
+            *self.waker.lock().unwrap() = Some(cx.waker().clone());
+            if self.set.load(SeqCst) {
+                return Poll::Ready(());
+            }
+            Poll::Pending
+        }).await
+    }
+}
+
+#[test]
+fn remote_wakes_with_idle_notification() {
+    const NUM: usize = 2000;
+    let events: Vec<_> = (0..NUM).map(|_| Event::new()).collect();
+    let done = Arc::new(AtomicUsize::new(0));
+    let ready = Arc::new(AtomicBool::new(false));
+    let executor = {
+        let (events, done, ready) = (events.clone(), done.clone(), ready.clone());
+        thread::spawn(move || {
+            runtime_init();
+            runtime_set_idle(IdleStrategy::Notification(HostNotification::new()));
+            for event in events {
+                let done = done.clone();
+                coroutine_spawn(Box::pin(async move {
+                    event.wait().await;
+                    done.fetch_add(1, SeqCst);
+                }));
+            }
+            coroutine_run_until_blocked();
+            ready.store(true, SeqCst);
+            // 执行器阻塞在 notification 上，只能被其他线程的唤醒叫醒
+            coroutine_run_until_complete();
+        })
+    };
+    while !ready.load(SeqCst) {
+        thread::yield_now();
+    }
+    let wakers: Vec<_> = events.chunks(NUM / 4).map(|chunk| {
+        let chunk = chunk.to_vec();
+        thread::spawn(move || {
+            for event in chunk.iter().rev() {
+                event.set();
+            }
+        })
+    }).collect();
+    for waker in wakers {
+        waker.join().unwrap();
+    }
+    executor.join().unwrap();
+    assert_eq!(done.load(SeqCst), NUM);
+}
+
+#[test]
+fn notification_idle_waits_for_timer_or_remote_wake() {
+    runtime_init();
+    runtime_set_idle(IdleStrategy::Notification(HostNotification::new()));
+    let start = std::time::Instant::now();
+    // 只有定时器时睡到定时器到期
+    block_on(Box::pin(sleep(2_000_000)));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(2));
+    // 等待远处的定时器期间，其他线程的唤醒能立即叫醒执行器
+    let far = coroutine_spawn(Box::pin(sleep(60_000_000_000)));
+    let event = Event::new();
+    let setter = event.clone();
+    let start = std::time::Instant::now();
+    let waker = thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(5));
+        setter.set();
+    });
+    block_on(Box::pin(async move { event.wait().await }));
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    waker.join().unwrap();
+    assert!(coroutine_abort(&far));
+}
+
+/// 共享协程的全局状态（执行器编号、共享队列、计数）在同一测试进程中共用，
+/// 使用共享协程的测试依次运行
+static SHARED_TESTS: Mutex<()> = Mutex::new(());
+
+/// 启动 num 个参与任务窃取的执行器线程，0 号执行器先运行 setup，各执行器运行到所有协程结束
+fn run_executors(num: usize, setup: impl FnOnce() + Send + 'static) {
+    let _serial = SHARED_TESTS.lock().unwrap_or_else(|err| err.into_inner());
+    let ready = Arc::new(AtomicUsize::new(0));
+    let mut setup = Some(setup);
+    let threads: Vec<_> = (0..num).map(|id| {
+        let ready = ready.clone();
+        let setup = if id == 0 { setup.take() } else { None };
+        thread::spawn(move || {
+            runtime_init();
+            runtime_set_idle(IdleStrategy::Notification(HostNotification::new()));
+            runtime_enable_multi_core(id);
+            EXECUTOR_ID.with(|cell| cell.set(id));
+            if let Some(setup) = setup {
+                setup();
+            }
+            ready.fetch_add(1, SeqCst);
+            while ready.load(SeqCst) < num {
+                thread::yield_now();
+            }
+            coroutine_run_until_complete();
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn shared_coroutines_are_stolen() {
+    const NUM: usize = 1000;
+    const EXECUTORS: usize = 4;
+    let done = Arc::new(AtomicUsize::new(0));
+    let ran_on = Arc::new(Mutex::new([0usize; EXECUTORS]));
+    let (done_clone, ran_on_clone) = (done.clone(), ran_on.clone());
+    run_executors(EXECUTORS, move || {
+        for _ in 0..NUM {
+            let (done, ran_on) = (done_clone.clone(), ran_on_clone.clone());
+            coroutine_spawn_shared(Box::pin(async move {
+                ran_on.lock().unwrap()[id_of_current_thread()] += 1;
+                thread::sleep(std::time::Duration::from_micros(20));
+                done.fetch_add(1, SeqCst);
+            }), 1);
+        }
+    });
+    assert_eq!(done.load(SeqCst), NUM);
+    let ran_on = ran_on.lock().unwrap();
+    assert_eq!(ran_on.iter().sum::<usize>(), NUM);
+    assert!(ran_on.iter().filter(|num| **num > 0).count() > 1, "no coroutine was stolen: {:?}", ran_on);
+}
+
+#[test]
+fn suspended_shared_coroutines_migrate() {
+    const NUM: usize = 200;
+    const ROUNDS: usize = 20;
+    const EXECUTORS: usize = 4;
+    let migrated = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicUsize::new(0));
+    let (migrated_clone, done_clone) = (migrated.clone(), done.clone());
+    run_executors(EXECUTORS, move || {
+        for _ in 0..NUM {
+            let (migrated, done) = (migrated_clone.clone(), done_clone.clone());
+            coroutine_spawn_shared(Box::pin(async move {
+                let mut last = id_of_current_thread();
+                for _ in 0..ROUNDS {
+                    // 被其他线程唤醒后可能在另一个执行器上继续运行
+                    let event = Event::new();
+                    let setter = event.clone();
+                    thread::spawn(move || setter.set());
+                    event.wait().await;
+                    let now = id_of_current_thread();
+                    if now != last {
+                        migrated.fetch_add(1, SeqCst);
+                        last = now;
+                    }
+                    thread::sleep(std::time::Duration::from_micros(10));
+                }
+                done.fetch_add(1, SeqCst);
+            }), 1);
+        }
+    });
+    assert_eq!(done.load(SeqCst), NUM);
+    assert!(migrated.load(SeqCst) > 0, "no started coroutine was stolen");
+}
+
+#[test]
+fn shared_sleep_fires_on_any_executor() {
+    const NUM: usize = 50;
+    let done = Arc::new(AtomicUsize::new(0));
+    let done_clone = done.clone();
+    run_executors(2, move || {
+        for i in 0..NUM {
+            let done = done_clone.clone();
+            coroutine_spawn_shared(Box::pin(async move {
+                sleep(10_000 * (i as u64 % 5)).await;
+                sleep(10_000).await;
+                done.fetch_add(1, SeqCst);
+            }), 2);
+        }
+    });
+    assert_eq!(done.load(SeqCst), NUM);
+}
+
+thread_local! {
+    static EXECUTOR_ID: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+fn id_of_current_thread() -> usize {
+    EXECUTOR_ID.with(|cell| cell.get())
+}
@@ -0,0 +1,139 @@
+//! 宿主机上的负载区测试：cargo test --no-default-features --features std
+#![cfg(not(loom))]
+
+use std::alloc::{alloc_zeroed, Layout};
+use async_runtime::*;
+
+fn new_arena() -> &'static PayloadArena {
+    unsafe { &*(alloc_zeroed(Layout::new::<PayloadArena>()) as *const PayloadArena) }
+}
+
+#[test]
+fn alloc_write_read_free() {
+    let arena = new_arena();
+    let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let payload = arena.alloc(data.len()).unwrap();
+    assert_eq!(payload.as_slice().len(), 1);
+    assert_eq!(payload.len(), data.len());
+    assert_eq!(arena.write(&payload, &data), Ok(data.len()));
+    let mut out = vec![0; data.len()];
+    assert_eq!(arena.read(&payload, &mut out), Ok(data.len()));
+    assert_eq!(out, data);
+    arena.free(&payload).unwrap();
+    // 释放后同一位置可以再次分配
+    assert_eq!(arena.alloc(data.len()).unwrap(), payload);
+    assert!(arena.alloc(0).is_err());
+    assert!(arena.alloc(PAYLOAD_ARENA_SIZE + 1).is_err());
+}
+
+#[test]
+fn fragmented_arena_uses_scatter_gather() {
+    let arena = new_arena();
+    let half = PAYLOAD_ARENA_SIZE / 2;
+    let quarter = PAYLOAD_ARENA_SIZE / 4;
+    let blocks: Vec<_> = (0..4).map(|_| arena.alloc(quarter).unwrap()).collect();
+    // 释放第 0 与第 2 块，剩下两段不相邻的空闲区
+    arena.free(&blocks[0]).unwrap();
+    arena.free(&blocks[2]).unwrap();
+    let payload = arena.alloc(half).unwrap();
+    assert_eq!(payload.as_slice().len(), 2);
+    assert_eq!(payload.as_slice()[0].offset as usize, 0);
+    assert_eq!(payload.as_slice()[1].offset as usize, 2 * quarter);
+    let data: Vec<u8> = (0..half).map(|i| (i % 251) as u8).collect();
+    assert_eq!(arena.write(&payload, &data), Ok(half));
+    let mut out = vec![0; half];
+    assert_eq!(arena.read(&payload, &mut out), Ok(half));
+    assert_eq!(out, data);
+    // 空间不足时失败且不泄漏已占用的块
+    assert!(arena.alloc(quarter + 1).is_err());
+    arena.free(&payload).unwrap();
+    assert_eq!(arena.alloc(quarter).unwrap().as_slice()[0].offset, 0);
+}
+
+#[test]
+fn too_many_fragments_fail_without_leaking() {
+    let arena = new_arena();
+    let blocks: Vec<_> = (0..PAYLOAD_ARENA_SIZE / PAYLOAD_BLOCK_SIZE).map(|_| arena.alloc(1).unwrap()).collect();
+    for block in blocks.iter().step_by(2) {
+        arena.free(block).unwrap();
+    }
+    // 需要 MAX_PAYLOAD_SEGMENTS + 1 个不相邻的空闲块
+    assert!(arena.alloc((MAX_PAYLOAD_SEGMENTS + 1) * PAYLOAD_BLOCK_SIZE).is_err());
+    let payload = arena.alloc(MAX_PAYLOAD_SEGMENTS * PAYLOAD_BLOCK_SIZE).unwrap();
+    assert_eq!(payload.as_slice().len(), MAX_PAYLOAD_SEGMENTS);
+    assert_eq!(payload.as_slice()[0].offset, 0);
+}
+
+#[test]
+fn receiver_rejects_out_of_bounds_descriptors() {
+    let arena = new_arena();
+    let inside = PayloadDesc { offset: (PAYLOAD_ARENA_SIZE - 16) as u32, len: 16 };
+    let past_end = PayloadDesc { offset: (PAYLOAD_ARENA_SIZE - 16) as u32, len: 17 };
+    let overflow = PayloadDesc { offset: u32::MAX, len: u32::MAX };
+    assert!(arena.validate(&inside).is_ok());
+    assert!(arena.validate(&past_end).is_err());
+    assert!(arena.validate(&overflow).is_err());
+    let mut list = PayloadList::new();
+    list.push(inside).unwrap();
+    list.push(past_end).unwrap();
+    let mut out = [0; 64];
+    assert!(arena.read(&list, &mut out).is_err());
+    assert!(arena.write(&list, &out).is_err());
+    assert!(arena.free(&list).is_err());
+}
+
+#[test]
+fn segment_access_stays_inside_descriptor() {
+    let arena = new_arena();
+    let payload = arena.alloc(300).unwrap();
+    let desc = payload.as_slice()[0];
+    arena.write_segment(&desc, 290, &[7; 10]).unwrap();
+    let mut out = [0; 4];
+    arena.read_segment(&desc, 296, &mut out).unwrap();
+    assert_eq!(out, [7; 4]);
+    assert!(arena.read_segment(&desc, 297, &mut out).is_err());
+    assert!(arena.write_segment(&desc, usize::MAX, &[1]).is_err());
+    assert!(arena.segment_ptr(&PayloadDesc { offset: PAYLOAD_ARENA_SIZE as u32, len: 1 }).is_err());
+}
+
+#[test]
+fn lease_returns_blocks_on_drop() {
+    let arena = new_arena();
+    let lease = arena.lease(PAYLOAD_ARENA_SIZE).unwrap();
+    assert_eq!(lease.list().len(), PAYLOAD_ARENA_SIZE);
+    assert!(arena.alloc(1).is_err());
+    drop(lease);
+    assert!(arena.alloc(1).is_ok());
+}
+
+#[test]
+fn descriptors_round_trip_through_ipc_item() {
+    let mut list = PayloadList::new();
+    list.push(PayloadDesc { offset: 0x12345, len: 0x10001 }).unwrap();
+    list.push(PayloadDesc { offset: 256, len: 3 }).unwrap();
+    let mut item = IPCItem::new();
+    item.extend_msg[0] = 7;
+    item.set_payload(3, &list).unwrap();
+    assert_eq!(item.extend_msg[0], 7);
+    assert_eq!(item.get_payload(3), Ok(list));
+    // 放不下的位置与对端伪造的区段数都会被拒绝
+    assert!(item.set_payload(MAX_IPC_MSG_LEN - 4, &list).is_err());
+    item.extend_msg[3] = (MAX_PAYLOAD_SEGMENTS + 1) as u16;
+    assert!(item.get_payload(3).is_err());
+    assert!(item.get_payload(MAX_IPC_MSG_LEN).is_err());
+}
+
+#[test]
+fn length_above_u16_survives_message_encoding() {
+    // 与网络消息相同的排布：extend_msg[1..3] 是长度，之后是区段列表
+    let arena = new_arena();
+    for len in [u16::MAX as usize, u16::MAX as usize + 1, PAYLOAD_ARENA_SIZE] {
+        let payload = arena.alloc(len).unwrap();
+        let mut item = IPCItem::new();
+        item.set_word32(1, payload.len() as u32);
+        item.set_payload(3, &payload).unwrap();
+        assert_eq!(item.get_word32(1) as usize, len);
+        assert_eq!(item.get_payload(3), Ok(payload));
+        arena.free(&payload).unwrap();
+    }
+}
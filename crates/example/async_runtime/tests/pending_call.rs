@@ -0,0 +1,189 @@
+//! 宿主机上的请求编号测试：cargo test --no-default-features --features std
+
+mod common;
+
+use std::alloc::{alloc_zeroed, Layout};
+use std::future::pending;
+use async_runtime::*;
+use async_runtime::platform::get_clock;
+use common::{spawn_with_output, Output};
+
+fn leak_calls() -> &'static PendingCalls {
+    Box::leak(Box::new(PendingCalls::new()))
+}
+
+fn tagged_reply(cid: CoroutineId, id: u16, label: u32) -> IPCItem {
+    let mut item = IPCItem::from(cid, label);
+    item.set_request_id(id);
+    item
+}
+
+#[test]
+fn request_id_shares_msg_info_with_label() {
+    let mut item = IPCItem::from(CoroutineId::from_parts(3, 1), 5);
+    item.set_caller_prio(3);
+    item.set_request_id(MAX_REQUEST_ID);
+    assert_eq!(item.label(), 5);
+    assert_eq!(item.request_id(), MAX_REQUEST_ID);
+    assert_eq!(item.caller_prio(), Some(3));
+    let reply = IPCItem::reply_to(&item, 6);
+    assert_eq!((reply.cid, reply.label(), reply.request_id()), (item.cid, 6, MAX_REQUEST_ID));
+    assert_eq!(reply.caller_prio(), None);
+}
+
+#[test]
+fn message_info_word_keeps_id_and_prio_bits() {
+    let mut item = IPCItem::from(CoroutineId(1), 7);
+    item.set_request_id(MAX_REQUEST_ID);
+    item.set_caller_prio(2);
+    // seL4 的 MessageInfo 字：标签在第 12 位以上，其下是 cap 数与长度
+    let word = 0xdead_beef_u64 << 12 | 0b101 << 9 | 0b11 << 7 | 120;
+    item.set_message_info_word(word);
+    assert_eq!(item.message_info_word(), word);
+    assert_eq!((item.label(), item.caller_prio(), item.request_id()), (7, Some(2), MAX_REQUEST_ID));
+    item.set_request_id(1);
+    assert_eq!(item.message_info_word(), word);
+}
+
+#[test]
+fn caller_prio_zero_is_distinct_from_absent() {
+    let mut item = IPCItem::from(CoroutineId::from_parts(3, 1), 5);
+    assert_eq!(item.caller_prio(), None);
+    item.set_caller_prio(0);
+    item.set_request_id(9);
+    assert_eq!((item.label(), item.caller_prio(), item.request_id()), (5, Some(0), 9));
+}
+
+#[test]
+fn cancel_names_the_request() {
+    let mut req = IPCItem::from(CoroutineId::from_parts(3, 1), 5);
+    req.set_request_id(42);
+    let cancel = IPCItem::cancel_of(&req);
+    assert!(cancel.is_cancel() && !req.is_cancel());
+    assert_eq!((cancel.cid, cancel.request_id(), cancel.label()), (req.cid, 42, CANCEL_LABEL));
+}
+
+#[test]
+fn pipelined_calls_get_their_own_replies() {
+    runtime_init();
+    let calls = leak_calls();
+    let sent = Output::new();
+    let result = Output::new();
+    let (sent_clone, result_clone) = (sent.clone(), result.clone());
+    coroutine_spawn(Box::pin(async move {
+        let cid = coroutine_get_current();
+        let first = calls.register(&cid).unwrap();
+        let second = calls.register(&cid).unwrap();
+        sent_clone.set((cid, first, second));
+        let (a, b) = join(calls.wait(first), calls.wait(second)).await;
+        result_clone.set((a.label(), b.label()));
+    }));
+    coroutine_run_until_blocked();
+    let (cid, first, second) = sent.take().unwrap();
+    assert_ne!(first, second);
+    // 回复乱序到达
+    let second_reply = tagged_reply(cid, second, 20);
+    assert_eq!(calls.complete(&second_reply), Ok(()));
+    assert_eq!(calls.complete(&second_reply), Err(ReplyError::Duplicate));
+    assert_eq!(calls.complete(&tagged_reply(cid, first, 10)), Ok(()));
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some((10, 20)));
+    assert!(calls.is_empty());
+    // 调用结束后到达的回复被丢弃
+    assert_eq!(calls.complete(&second_reply), Err(ReplyError::UnknownRequest));
+    assert_eq!(calls.dropped_num(), 2);
+}
+
+#[test]
+fn stray_replies_are_rejected() {
+    let calls = leak_calls();
+    let owner = CoroutineId::from_parts(1, 0);
+    let id = calls.register(&owner).unwrap();
+    assert_ne!(id, 0);
+    assert_eq!(calls.complete(&tagged_reply(CoroutineId::from_parts(2, 0), id, 1)), Err(ReplyError::WrongCoroutine));
+    assert_eq!(calls.complete(&IPCItem::from(owner, 1)), Err(ReplyError::UnknownRequest));
+    assert_eq!(calls.complete(&tagged_reply(owner, id, 1)), Ok(()));
+    assert_eq!(calls.dropped_num(), 2);
+    calls.cancel(id);
+    assert!(calls.is_empty());
+}
+
+#[test]
+fn register_skips_ids_in_use() {
+    let calls = leak_calls();
+    let cid = CoroutineId::from_parts(1, 0);
+    let ids: Vec<u16> = (0..MAX_REQUEST_ID).map(|_| calls.register(&cid).unwrap()).collect();
+    assert!(ids.iter().all(|&id| id != 0 && id <= MAX_REQUEST_ID));
+    assert_eq!(calls.register(&cid), Err(()));
+    calls.cancel(ids[7]);
+    assert_eq!(calls.register(&cid), Ok(ids[7]));
+}
+
+#[test]
+fn dropped_call_deregisters() {
+    runtime_init();
+    let calls = leak_calls();
+    let id = Output::new();
+    let id_clone = id.clone();
+    let cid = coroutine_spawn(Box::pin(async move {
+        let call = calls.register(&coroutine_get_current()).unwrap();
+        id_clone.set(call);
+        calls.wait(call).await;
+        pending::<()>().await;
+    }));
+    coroutine_run_until_blocked();
+    assert_eq!(calls.len(), 1);
+    assert!(coroutine_abort(&cid));
+    assert!(calls.is_empty());
+    let late = tagged_reply(cid, id.take().unwrap(), 0);
+    assert_eq!(calls.complete(&late), Err(ReplyError::UnknownRequest));
+}
+
+#[test]
+fn timed_out_call_drops_late_reply() {
+    runtime_init();
+    let calls = leak_calls();
+    let sent = Output::new();
+    let sent_clone = sent.clone();
+    let result = spawn_with_output(async move {
+        let cid = coroutine_get_current();
+        let id = calls.register(&cid).unwrap();
+        sent_clone.set((cid, id));
+        timeout_at(calls.wait(id), get_clock() + 1_000_000).await.is_err()
+    });
+    coroutine_run_until_complete();
+    assert_eq!(result.take(), Some(true));
+    assert!(calls.is_empty());
+    let (cid, id) = sent.take().unwrap();
+    assert_eq!(calls.complete(&tagged_reply(cid, id, 1)), Err(ReplyError::UnknownRequest));
+}
+
+#[test]
+fn abandoned_call_keeps_payload_until_server_answers() {
+    let calls = leak_calls();
+    let arena = unsafe { &*(alloc_zeroed(Layout::new::<PayloadArena>()) as *const PayloadArena) };
+    let cid = CoroutineId::from_parts(1, 0);
+    for acked_by_cancel in [false, true] {
+        let id = calls.register(&cid).unwrap();
+        let req = tagged_reply(cid, id, 1);
+        calls.abandon(id, arena.lease(PAYLOAD_ARENA_SIZE).unwrap());
+        // 等待者随后注销，被放弃的调用仍然保留
+        calls.cancel(id);
+        assert_eq!(calls.len(), 1);
+        assert!(arena.alloc(1).is_err());
+        let answer = if acked_by_cancel { IPCItem::cancel_of(&req) } else { IPCItem::reply_to(&req, 2) };
+        assert_eq!(calls.complete(&answer), Err(ReplyError::Abandoned));
+        assert!(calls.is_empty());
+        arena.free(&arena.alloc(PAYLOAD_ARENA_SIZE).unwrap()).unwrap();
+    }
+}
+
+#[test]
+fn cancel_ack_is_not_a_reply() {
+    let calls = leak_calls();
+    let cid = CoroutineId::from_parts(1, 0);
+    let id = calls.register(&cid).unwrap();
+    let req = tagged_reply(cid, id, 1);
+    assert_eq!(calls.complete(&IPCItem::cancel_of(&req)), Err(ReplyError::UnknownRequest));
+    assert_eq!(calls.complete(&IPCItem::reply_to(&req, 2)), Ok(()));
+}
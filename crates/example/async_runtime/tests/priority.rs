@@ -0,0 +1,103 @@
+//! 宿主机上的优先级与优先级继承测试：cargo test --no-default-features --features std
+
+mod common;
+
+use std::future::pending;
+use std::sync::{Arc, Mutex};
+use async_runtime::*;
+use common::{spawn_with_output, yield_once, Output};
+
+/// 一直挂起的协程
+fn spawn_parked(prio: usize) -> CoroutineId {
+    coroutine_spawn_with_prio(Box::pin(pending::<()>()), prio)
+}
+
+fn prio_of(cid: &CoroutineId) -> usize {
+    coroutine_prio(cid).unwrap()
+}
+
+#[test]
+fn set_prio_rejects_out_of_range() {
+    runtime_init();
+    let cid = spawn_parked(3);
+    assert!(!coroutine_set_prio(&cid, MAX_PRIO_NUM));
+    assert_eq!(prio_of(&cid), 3);
+    assert!(coroutine_set_prio(&cid, 2));
+    assert_eq!(prio_of(&cid), 2);
+}
+
+#[test]
+fn set_prio_moves_queued_coroutine() {
+    runtime_init();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut cids = Vec::new();
+    for i in 0..3 {
+        let log = log.clone();
+        cids.push(coroutine_spawn_with_prio(Box::pin(async move { log.lock().unwrap().push(i) }), 3));
+    }
+    assert!(coroutine_set_prio(&cids[2], 0));
+    coroutine_run_until_complete();
+    assert_eq!(*log.lock().unwrap(), vec![2, 0, 1]);
+}
+
+#[test]
+fn boosts_stack_and_survive_set_prio() {
+    runtime_init();
+    coroutine_set_prio_inherit(true);
+    let cid = spawn_parked(5);
+    let first = coroutine_inherit_prio(&cid, 2).unwrap();
+    let second = coroutine_inherit_prio(&cid, 1).unwrap();
+    assert_eq!(prio_of(&cid), 1);
+    // 修改基础优先级不会丢掉仍在生效的继承
+    assert!(coroutine_set_prio(&cid, 4));
+    assert_eq!(prio_of(&cid), 1);
+    drop(second);
+    assert_eq!(prio_of(&cid), 2);
+    drop(first);
+    assert_eq!(prio_of(&cid), 4);
+}
+
+#[test]
+fn inherit_prio_is_opt_in() {
+    runtime_init();
+    let cid = spawn_parked(5);
+    assert!(coroutine_inherit_prio(&cid, 0).is_none());
+    assert_eq!(prio_of(&cid), 5);
+}
+
+#[test]
+fn join_handle_boosts_until_dropped() {
+    runtime_init();
+    coroutine_set_prio_inherit(true);
+    let seen = Output::new();
+    let seen_clone = seen.clone();
+    let child = coroutine_spawn_joinable_with_prio(Box::pin(async move {
+        yield_once().await;
+        seen_clone.set(coroutine_current_prio());
+        pending::<()>().await;
+    }), 6);
+    let child_cid = child.cid();
+    let parent = coroutine_spawn(Box::pin(async move {
+        let _ = child.await;
+    }));
+    coroutine_run_until_blocked();
+    assert_eq!(seen.take(), Some(Some(DEFAULT_PRIO)));
+    assert_eq!(prio_of(&child_cid), DEFAULT_PRIO);
+    // 等待者被取消，句柄随之释放并撤销继承
+    assert!(coroutine_abort(&parent));
+    assert_eq!(prio_of(&child_cid), 6);
+}
+
+#[test]
+fn no_current_prio_outside_coroutines() {
+    runtime_init();
+    spawn_parked(3);
+    coroutine_run_until_blocked();
+    // 协程挂起后执行器不再有当前协程
+    assert_eq!(coroutine_current_prio(), None);
+    let mut group = TaskGroup::<usize>::new();
+    group.spawn(Box::pin(async { coroutine_current_prio().unwrap() }));
+    let output = spawn_with_output(async move { group.join_all().await });
+    coroutine_run_until_blocked();
+    assert_eq!(output.take().map(|res| res.unwrap()), Some(vec![DEFAULT_PRIO]));
+}
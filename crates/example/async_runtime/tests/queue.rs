@@ -0,0 +1,145 @@
+//! 宿主机上的共享队列压力测试：cargo test --no-default-features --features std
+#![cfg(not(loom))]
+
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+use std::mem::size_of;
+use async_runtime::queue::SpscQueue;
+use async_runtime::{CoroutineId, IPCItem, ItemsQueue, KernelBuffer, NewBuffer, MAX_ITEM_NUM};
+use common::leak_new_buffer;
+
+/// 由生产者编号与序号生成的元素，每个字都可以校验，用来发现写了一半的元素
+fn make_item(producer: usize, seq: usize) -> IPCItem {
+    let mut item = IPCItem::from(CoroutineId(producer as u32), seq as u32);
+    for (i, word) in item.extend_msg.iter_mut().enumerate() {
+        *word = (seq as u16).wrapping_mul(31).wrapping_add(i as u16);
+    }
+    item
+}
+
+fn check_item(item: &IPCItem) -> (usize, usize) {
+    let seq = item.msg_info as usize;
+    assert_eq!(*item, make_item(item.cid.0 as usize, seq), "garbled item");
+    (item.cid.0 as usize, seq)
+}
+
+#[test]
+fn spsc_wraps_around_and_reports_full() {
+    let queue = SpscQueue::<usize, 4>::new();
+    for round in 0..10 {
+        for i in 0..4 {
+            queue.push(&(round * 4 + i)).unwrap();
+        }
+        assert_eq!(queue.push(&0), Err(()));
+        assert_eq!(queue.len(), 4);
+        for i in 0..4 {
+            assert_eq!(queue.pop(), Some(round * 4 + i));
+        }
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+}
+
+#[test]
+fn take_reports_length_before_pop() {
+    let queue = SpscQueue::<usize, 4>::new();
+    for i in 0..4 {
+        assert_eq!(queue.push(&i), Ok(i));
+    }
+    assert_eq!(queue.take(), Some((0, 4)));
+    assert_eq!(queue.take(), Some((1, 3)));
+}
+
+#[test]
+fn new_buffer_keeps_kernel_layout() {
+    // 与内核中的 SafeRingBuffer<IPCItem, MAX_ITEM_NUM> 一致：元素数组加 start、end、count 三个字
+    assert_eq!(size_of::<IPCItem>(), 40);
+    assert_eq!(size_of::<ItemsQueue>(), MAX_ITEM_NUM * size_of::<IPCItem>() + 3 * size_of::<usize>());
+    // 内核已知的部分在页首，用户态的锁字和负载区都在它之后
+    let buffer = leak_new_buffer();
+    let base = buffer as *const NewBuffer as usize;
+    let kernel: &KernelBuffer = buffer;
+    assert_eq!(kernel as *const KernelBuffer as usize, base);
+    for field in [&kernel.req_items as *const _ as usize, &kernel.res_items as *const _ as usize] {
+        assert!(field + size_of::<ItemsQueue>() <= base + size_of::<KernelBuffer>());
+    }
+    assert!(&buffer.payload as *const _ as usize >= base + size_of::<KernelBuffer>());
+}
+
+#[test]
+fn zeroed_new_buffer_is_empty_and_usable() {
+    let buffer = leak_new_buffer();
+    assert!(buffer.req_queue().is_empty());
+    assert_eq!(buffer.req_queue().get_first_item(), None);
+    for seq in 0..3 * MAX_ITEM_NUM {
+        buffer.res_queue().write_free_item(&make_item(0, seq)).unwrap();
+        assert_eq!(check_item(&buffer.res_queue().get_first_item().unwrap()), (0, seq));
+    }
+}
+
+#[test]
+fn spsc_stress_keeps_order() {
+    const NUM: usize = 200_000;
+    let queue = Arc::new(SpscQueue::<IPCItem, 64>::new());
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            for seq in 0..NUM {
+                let item = make_item(0, seq);
+                while queue.push(&item).is_err() {
+                    thread::yield_now();
+                }
+            }
+        })
+    };
+    let mut expected = 0;
+    while expected < NUM {
+        match queue.pop() {
+            Some(item) => {
+                assert_eq!(check_item(&item), (0, expected));
+                expected += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    producer.join().unwrap();
+    assert_eq!(queue.pop(), None);
+}
+
+#[test]
+fn items_queue_serializes_local_writers() {
+    const WRITERS: usize = 4;
+    const PER_WRITER: usize = 50_000;
+    let buffer = leak_new_buffer();
+    let writers: Vec<_> = (0..WRITERS).map(|writer| {
+        thread::spawn(move || {
+            for seq in 0..PER_WRITER {
+                let item = make_item(writer, seq);
+                while buffer.req_queue().write_free_item(&item).is_err() {
+                    thread::yield_now();
+                }
+            }
+        })
+    }).collect();
+    let mut next = [0; WRITERS];
+    let mut received = 0;
+    while received < WRITERS * PER_WRITER {
+        match buffer.req_queue().get_first_item() {
+            Some(item) => {
+                let (writer, seq) = check_item(&item);
+                // 同一写者的元素按写入顺序出队
+                assert_eq!(next[writer], seq);
+                next[writer] += 1;
+                received += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert!(buffer.req_queue().is_empty());
+    assert_eq!(next, [PER_WRITER; WRITERS]);
+}
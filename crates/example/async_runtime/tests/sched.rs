@@ -0,0 +1,19 @@
+//! 宿主机上的调度策略测试：cargo test --no-default-features --features std
+
+use async_runtime::*;
+
+#[test]
+fn edf_forgets_deadline_of_departed_coroutine() {
+    let mut policy = EarliestDeadlineFirstPolicy::new();
+    let first = CoroutineId::from_parts(1, 0);
+    let second = CoroutineId::from_parts(2, 0);
+    policy.push(first, 0);
+    assert_eq!(policy.pop(), Some(first));
+    // 截止时间更晚的协程不能抢占正在运行的协程
+    policy.push(second, MAX_PRIO_NUM - 1);
+    assert!(!policy.preempt_possible(0));
+    // 离开 CPU 后不再用它的截止时间比较
+    policy.leave();
+    assert!(policy.preempt_possible(0));
+    assert_eq!(policy.pop(), Some(second));
+}
@@ -0,0 +1,170 @@
+//! 宿主机上的同步原语测试：cargo test --no-default-features --features std
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::Arc;
+use async_runtime::sync::{mpsc, oneshot, Mutex, Notify, RwLock, Semaphore};
+use async_runtime::*;
+use common::yield_once;
+
+#[test]
+fn mutex_serializes_critical_sections() {
+    runtime_init();
+    let counter = Arc::new(Mutex::new(0usize));
+    let inside = Arc::new(AtomicUsize::new(0));
+    for _ in 0..32 {
+        let (counter, inside) = (counter.clone(), inside.clone());
+        coroutine_spawn(Box::pin(async move {
+            for _ in 0..8 {
+                let mut guard = counter.lock().await;
+                assert_eq!(inside.fetch_add(1, SeqCst), 0);
+                let value = *guard;
+                // 持有锁时让出，其他协程只能排队等待
+                yield_once().await;
+                *guard = value + 1;
+                inside.fetch_sub(1, SeqCst);
+            }
+        }));
+    }
+    coroutine_run_until_complete();
+    assert_eq!(*counter.try_lock().unwrap(), 32 * 8);
+}
+
+#[test]
+fn blocking_lock_waits_for_other_thread() {
+    let counter = Arc::new(Mutex::new(0usize));
+    let guard = counter.try_lock().unwrap();
+    let other = {
+        let counter = counter.clone();
+        std::thread::spawn(move || {
+            *counter.blocking_lock() += 1;
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert!(!other.is_finished());
+    drop(guard);
+    other.join().unwrap();
+    assert_eq!(*counter.blocking_lock(), 1);
+}
+
+#[test]
+fn rwlock_allows_concurrent_readers() {
+    runtime_init();
+    let lock = Arc::new(RwLock::new(1usize));
+    let readers = Arc::new(AtomicUsize::new(0));
+    let max_readers = Arc::new(AtomicUsize::new(0));
+    for _ in 0..4 {
+        let (lock, readers, max_readers) = (lock.clone(), readers.clone(), max_readers.clone());
+        coroutine_spawn(Box::pin(async move {
+            let guard = lock.read().await;
+            let now = readers.fetch_add(1, SeqCst) + 1;
+            max_readers.fetch_max(now, SeqCst);
+            yield_once().await;
+            assert_eq!(*guard, 1);
+            readers.fetch_sub(1, SeqCst);
+        }));
+    }
+    let writer_lock = lock.clone();
+    coroutine_spawn(Box::pin(async move {
+        *writer_lock.write().await += 1;
+    }));
+    coroutine_run_until_complete();
+    assert_eq!(max_readers.load(SeqCst), 4);
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn semaphore_limits_and_closes() {
+    runtime_init();
+    let semaphore = Arc::new(Semaphore::new(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    for _ in 0..8 {
+        let (semaphore, running) = (semaphore.clone(), running.clone());
+        coroutine_spawn(Box::pin(async move {
+            semaphore.acquire(1).await.unwrap();
+            assert!(running.fetch_add(1, SeqCst) < 2);
+            yield_once().await;
+            running.fetch_sub(1, SeqCst);
+            semaphore.release(1);
+        }));
+    }
+    coroutine_run_until_complete();
+    assert_eq!(semaphore.available_permits(), 2);
+
+    let closed = Arc::new(AtomicUsize::new(0));
+    let (waiter_semaphore, waiter_closed) = (semaphore.clone(), closed.clone());
+    coroutine_spawn(Box::pin(async move {
+        if waiter_semaphore.acquire(3).await.is_err() {
+            waiter_closed.fetch_add(1, SeqCst);
+        }
+    }));
+    coroutine_run_until_blocked();
+    semaphore.close();
+    coroutine_run_until_complete();
+    assert_eq!(closed.load(SeqCst), 1);
+}
+
+#[test]
+fn notify_wakes_waiters() {
+    runtime_init();
+    let notify = Arc::new(Notify::new());
+    let woken = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let (notify, woken) = (notify.clone(), woken.clone());
+        coroutine_spawn(Box::pin(async move {
+            notify.notified().await;
+            woken.fetch_add(1, SeqCst);
+        }));
+    }
+    coroutine_run_until_blocked();
+    notify.notify_one();
+    coroutine_run_until_blocked();
+    assert_eq!(woken.load(SeqCst), 1);
+    notify.notify_waiters();
+    coroutine_run_until_complete();
+    assert_eq!(woken.load(SeqCst), 3);
+}
+
+#[test]
+fn oneshot_delivers_or_reports_drop() {
+    runtime_init();
+    let (sender, receiver) = oneshot::channel::<usize>();
+    let (dropped_sender, dropped_receiver) = oneshot::channel::<usize>();
+    let handle = coroutine_spawn_joinable(Box::pin(async move {
+        (receiver.await, dropped_receiver.await)
+    }));
+    coroutine_run_until_blocked();
+    sender.send(5).unwrap();
+    drop(dropped_sender);
+    let result = block_on(Box::pin(handle));
+    assert_eq!(result, Ok((Ok(5), Err(()))));
+}
+
+#[test]
+fn mpsc_bounded_channel_keeps_order() {
+    runtime_init();
+    const PRODUCERS: usize = 4;
+    const PER_PRODUCER: usize = 200;
+    let (sender, mut receiver) = mpsc::channel::<(usize, usize)>(8);
+    for producer in 0..PRODUCERS {
+        let sender = sender.clone();
+        coroutine_spawn(Box::pin(async move {
+            for i in 0..PER_PRODUCER {
+                sender.send((producer, i)).await.unwrap();
+            }
+        }));
+    }
+    drop(sender);
+    let handle = coroutine_spawn_joinable(Box::pin(async move {
+        let mut next = [0; PRODUCERS];
+        while let Some((producer, i)) = receiver.recv().await {
+            // 同一发送者的消息按发送顺序到达
+            assert_eq!(next[producer], i);
+            next[producer] += 1;
+        }
+        next
+    }));
+    coroutine_run_until_complete();
+    assert_eq!(handle.try_take(), Some(Ok([PER_PRODUCER; PRODUCERS])));
+}
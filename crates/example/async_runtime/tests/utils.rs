@@ -0,0 +1,112 @@
+//! 宿主机上的数据结构测试：cargo test --no-default-features --features std
+
+use std::sync::Arc;
+use std::thread;
+use async_runtime::utils::{AtomicBitMap, BitMap, BitMap4096, BitMap64, IndexAllocator, RingBuffer};
+
+#[test]
+fn index_allocator_reuses_released_index() {
+    let mut allocator = IndexAllocator::<100>::new();
+    for i in 0..100 {
+        assert_eq!(allocator.allocate(), Some(i));
+    }
+    assert_eq!(allocator.allocate(), None);
+    allocator.release(42);
+    assert_eq!(allocator.allocate(), Some(42));
+    assert_eq!(allocator.allocate(), None);
+}
+
+#[test]
+fn index_allocator_never_exceeds_size() {
+    let mut allocator = IndexAllocator::<65>::new();
+    let mut allocated: Vec<_> = core::iter::from_fn(|| allocator.allocate()).collect();
+    allocated.sort();
+    assert_eq!(allocated, (0..65).collect::<Vec<_>>());
+}
+
+#[test]
+fn bitmap64_find_first() {
+    let mut bitmap = BitMap64::new();
+    assert!(bitmap.empty());
+    assert_eq!(bitmap.find_first_one(), 64);
+    bitmap.set(5);
+    bitmap.set(63);
+    assert_eq!(bitmap.find_first_one(), 5);
+    assert_eq!(bitmap.find_first_zero(), 0);
+    bitmap.clear(5);
+    assert_eq!(bitmap.find_first_one(), 63);
+}
+
+#[test]
+fn bitmap4096_fetch_in_order() {
+    let mut bitmap = BitMap4096::new();
+    for pos in [4095, 0, 64, 1000] {
+        bitmap.set(pos);
+    }
+    assert!(bitmap.get(1000));
+    let mut fetched = Vec::new();
+    while let Some(pos) = bitmap.fetch() {
+        fetched.push(pos);
+    }
+    assert_eq!(fetched, vec![0, 64, 1000, 4095]);
+    assert!(bitmap.empty());
+}
+
+#[test]
+fn ring_buffer_fifo_and_remove() {
+    // 留空一个位置区分空与满，容量为 SIZE - 1
+    let mut buffer = RingBuffer::<usize, 5>::new();
+    assert!(buffer.pop().is_none());
+    for i in 0..4 {
+        buffer.push(&i).unwrap();
+    }
+    assert!(buffer.full());
+    assert!(buffer.push(&4).is_err());
+    assert!(buffer.remove(&1));
+    assert!(!buffer.remove(&1));
+    buffer.push(&4).unwrap();
+    let items: Vec<_> = core::iter::from_fn(|| buffer.pop()).collect();
+    assert_eq!(items, vec![0, 2, 3, 4]);
+}
+
+#[test]
+fn atomic_bitmap_drain() {
+    let bitmap = AtomicBitMap::<65536>::new();
+    assert!(bitmap.empty());
+    for pos in [65535, 0, 4096, 4097, 12345] {
+        bitmap.set(pos);
+    }
+    let mut drained = Vec::new();
+    bitmap.drain(|pos| drained.push(pos));
+    assert_eq!(drained, vec![0, 4096, 4097, 12345, 65535]);
+    assert!(bitmap.empty());
+}
+
+#[test]
+fn atomic_bitmap_concurrent_set_loses_nothing() {
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 2048;
+    let bitmap = Arc::new(AtomicBitMap::<{ THREADS * PER_THREAD }>::new());
+    let producers: Vec<_> = (0..THREADS).map(|t| {
+        let bitmap = bitmap.clone();
+        thread::spawn(move || {
+            for i in 0..PER_THREAD {
+                bitmap.set(i * THREADS + t);
+            }
+        })
+    }).collect();
+    // 生产者运行期间消费者持续取出，每个位置恰好出现一次
+    let mut seen = vec![false; THREADS * PER_THREAD];
+    let drain = |seen: &mut Vec<bool>| bitmap.drain(|pos| {
+        assert!(!seen[pos], "position {} drained twice", pos);
+        seen[pos] = true;
+    });
+    while producers.iter().any(|producer| !producer.is_finished()) {
+        drain(&mut seen);
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    drain(&mut seen);
+    assert!(seen.iter().all(|seen| *seen));
+}
@@ -4,10 +4,10 @@ use sel4_root_task::debug_println;
 use spin::mutex::Mutex;
 use core::future::Future;
 use core::pin::Pin;
-use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
 use core::sync::atomic::Ordering::SeqCst;
 use core::task::{Context, Poll};
-use async_runtime::{coroutine_delay_wake, coroutine_get_current, coroutine_possible_switch, coroutine_wake, AsyncMessageLabel, CoroutineId, IPCItem, NewBuffer, MAX_TASK_NUM};
+use async_runtime::{coroutine_current_prio, coroutine_delay_wake, coroutine_get_current, coroutine_possible_switch, coroutine_wake, timeout_at, AsyncMessageLabel, CoroutineId, CANCEL_LABEL, IPCItem, Mailbox, NewBuffer, PayloadLease, PendingCalls, DEFAULT_MAILBOX_CAPACITY};
 use async_runtime::utils::{IndexAllocator};
 use sel4::{CPtr, CPtrBits, CapRights, LocalCPtr, MessageInfo, Notification, TCB};
 use sel4::sys::invocation_label;
@@ -31,8 +31,13 @@ pub type SenderID = i64;
 static mut SENDER_MAP: [usize; 64] = [0; 64];
 // static mut SENDER_MAP: BTreeMap<SenderID, &'static mut NewBuffer> = BTreeMap::new();
 
+/// 协程被唤醒时附带的 IPC 消息
 #[thread_local]
-static mut IMMEDIATE_VALUE: [Option<IPCItem>; MAX_TASK_NUM] = [None; MAX_TASK_NUM];
+static MAILBOX: Mailbox<IPCItem> = Mailbox::new(DEFAULT_MAILBOX_CAPACITY);
+
+/// 本线程发出的、尚未收到回复的调用
+#[thread_local]
+static PENDING_CALLS: PendingCalls = PendingCalls::new();
 
 pub type UIntVec = usize;
 
@@ -49,6 +54,20 @@ pub fn register_recv_cid(cid: &CoroutineId) -> Option<UIntVec> {
     }
 }
 
+/// 协程结束或被取消后清除其邮箱与中断唤醒注册，避免残留的消息占用内存或影响复用该槽位的新协程
+pub fn clear_coroutine_state(cid: &CoroutineId) {
+    MAILBOX.clear(cid);
+    unsafe {
+        WAKE_MAP.retain(|vec, wake_cid| {
+            if *wake_cid == *cid {
+                UINT_VEC_ALLOCATOR.release(*vec);
+                return false;
+            }
+            true
+        });
+    }
+}
+
 pub fn register_sender_buffer(ntfn: Notification, new_buffer: &'static mut NewBuffer) -> Result<SenderID, ()> {
     if let Ok(sender_id) = register_sender(ntfn) {
         // unsafe { SENDER_MAP.insert(sender_id as SenderID, new_buffer); }
@@ -123,17 +142,19 @@ impl AsyncArgs {
 pub async fn yield_now() -> Option<IPCItem> {
     let helper = YieldHelper::new();
     helper.await;
-    unsafe {
-        IMMEDIATE_VALUE[coroutine_get_current().0 as usize].take()
-    }
+    MAILBOX.try_recv(&coroutine_get_current())
 }
 
+/// 等待下一条投递给当前协程的消息
 #[inline]
-pub fn wake_with_value(cid: &CoroutineId, item: &IPCItem) {
-    unsafe {
-        IMMEDIATE_VALUE[cid.0 as usize] = Some(*item);
-        coroutine_wake(&cid);
-    }
+pub async fn recv_item() -> IPCItem {
+    MAILBOX.recv().await
+}
+
+/// 向协程邮箱投递消息并唤醒它；协程已结束或邮箱已满时返回 false
+#[inline]
+pub fn wake_with_value(cid: &CoroutineId, item: &IPCItem) -> bool {
+    MAILBOX.send(cid, *item).is_ok()
 }
 
 #[inline]
@@ -170,39 +191,36 @@ impl Future for YieldHelper {
 
 
 #[inline]
+/// 以 IPCItem 的形式发送 MessageInfo：完整的 MessageInfo 字（标签、cap 数、长度）放在 extend_msg 中，
+/// msg_info 中另外携带标签供服务端分发，请求编号与优先级在各自的位上。
+/// msg_info 的标签只有 16 位且 CANCEL_LABEL 保留给取消请求，超出范围的标签返回 Err。
+/// 回复同样在 extend_msg 中携带完整的 MessageInfo 字
 pub async fn seL4_Call(sender_id: &SenderID, mut message_info: MessageInfo) -> Result<MessageInfo, ()> {
-    let req_item = IPCItem::from(coroutine_get_current(), message_info.inner().0.inner()[0] as u32);
-    match seL4_Call_with_item(sender_id, &req_item).await {
-        Ok(res) => {
-            // let mut reply = MessageInfo::new(0, 0, 0, 0);
-            message_info.inner_mut().0.inner_mut()[0] = res.msg_info as u64;
-            Ok(message_info)
-        }
-        _ => {
-            Err(())
-        }
+    let label = message_info.label();
+    if label >= CANCEL_LABEL as u64 {
+        return Err(());
     }
+    let mut req_item = IPCItem::from(coroutine_get_current(), label as u32);
+    req_item.set_message_info_word(message_info.inner().0.inner()[0]);
+    let res = seL4_Call_with_item(sender_id, &req_item).await?;
+    message_info.inner_mut().0.inner_mut()[0] = res.message_info_word();
+    Ok(message_info)
 }
 
 
-pub async fn recv_reply_coroutine(arg: usize, reply_num: usize) {
+/// 客户端的回复分发协程，不会自行结束；驱动程序等待自己发起的调用完成即可
+pub async fn recv_reply_coroutine(arg: usize) {
     // let cid = coroutine_get_current();
-    static mut REPLY_COUNT: usize = 0;
     let async_args = AsyncArgs::from_ptr(arg);
     let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
     loop {
-        if let Some(item) = new_buffer.res_items.get_first_item() {
+        // 服务端取走请求后经 uipi 唤醒本协程，由这里唤醒因请求队列满而挂起的协程
+        new_buffer.req_queue().wake_writers();
+        if let Some(item) = recv_reply(&async_args.client_sender_id.unwrap(), new_buffer) {
             // debug_println!("recv req: {:?}", item);
             // coroutine_wake_with_value(&item.cid, item.msg_info as u64);
-            unsafe {
-                IMMEDIATE_VALUE[item.cid.0 as usize] = Some(item);
-                coroutine_wake(&item.cid);
-            }
-            unsafe {
-                REPLY_COUNT += 1;
-                if REPLY_COUNT == reply_num {
-                    break;
-                }
+            if let Err(err) = PENDING_CALLS.complete(&item) {
+                debug_println!("drop reply ({:?}): {:?}", err, item);
             }
         } else {
             new_buffer.recv_reply_status.store(false, SeqCst);
@@ -212,13 +230,14 @@ pub async fn recv_reply_coroutine(arg: usize, reply_num: usize) {
     }
 }
 
-pub async fn recv_reply_coroutine_async_syscall(new_buffer_ptr: usize, reply_num: usize) {
+/// 异步系统调用的回复分发协程，同样不会自行结束
+pub async fn recv_reply_coroutine_async_syscall(new_buffer_ptr: usize) {
     // let cid = coroutine_get_current();
-    #[thread_local]
-    static mut REPLY_COUNT: usize = 0;
     let new_buffer = NewBuffer::from_ptr(new_buffer_ptr);
     loop {
-        if let Some(item) = new_buffer.res_items.get_first_item() {
+        // 内核每处理完一个请求都会回复，收到回复即说明请求队列有了空位
+        new_buffer.req_queue().wake_writers();
+        if let Some(item) = new_buffer.res_queue().get_first_item() {
             // debug_println!("recv req: {:?}", item);
             // coroutine_wake_with_value(&item.cid, item.msg_info as u64);
             // unsafe {
@@ -226,7 +245,7 @@ pub async fn recv_reply_coroutine_async_syscall(new_buffer_ptr: usize, reply_num
             //     coroutine_wake(&item.cid);
             // }
             // debug_println!("recv_reply_coroutine_async_syscall: get item: {:?}", item);
-            let label: AsyncMessageLabel = AsyncMessageLabel::from(item.msg_info);
+            let label: AsyncMessageLabel = AsyncMessageLabel::from(item.label());
             match label {
                 AsyncMessageLabel::RISCVPageGetAddress => {
                     let mut paddr: usize = 0;
@@ -239,13 +258,8 @@ pub async fn recv_reply_coroutine_async_syscall(new_buffer_ptr: usize, reply_num
                 _ => {
                 }
             }
-            wake_with_value(&item.cid, &item);
-            unsafe {
-                REPLY_COUNT += 1;
-                // debug_println!("Reply count: {:?}", REPLY_COUNT);
-                if REPLY_COUNT == reply_num {
-                    break;
-                }
+            if !wake_with_value(&item.cid, &item) {
+                debug_println!("drop async syscall reply: {:?}", item);
             }
         } else {
             new_buffer.recv_reply_status.store(false, SeqCst);
@@ -306,58 +320,160 @@ fn convert_option_mut_ref<T>(ptr: usize) -> Option<&'static mut T> {
     })
 }
 
+/// sender_id 对应的共享缓冲区
+pub fn sender_buffer(sender_id: &SenderID) -> Option<&'static NewBuffer> {
+    unsafe { convert_option_mut_ref::<NewBuffer>(SENDER_MAP[*sender_id as usize]).map(|new_buffer| &*new_buffer) }
+}
+
 pub static mut SUBMIT_SYSCALL_CNT: usize = 0;
 
+/// 写入请求后通知服务端。服务端正在处理请求时省去中断；
+/// force 用于通知因回复队列满而挂起的服务端，此时服务端可能仍标记为活跃，必须发送中断
+fn notify_server(sender_id: &SenderID, new_buffer: &NewBuffer, force: bool) {
+    if new_buffer.recv_req_status.swap(true, SeqCst) && !force {
+        return;
+    }
+    if *sender_id != 63 {
+        unsafe {
+            uipi_send(*sender_id as u64);
+        }
+    } else {
+        unsafe {
+            SUBMIT_SYSCALL_CNT += 1;
+        }
+        wake_syscall_handler();
+    }
+}
+
+/// 写入回复后通知客户端，force 的含义同 notify_server
+pub fn notify_client(server_sender_id: SenderID, new_buffer: &NewBuffer, force: bool) {
+    if new_buffer.recv_reply_status.swap(true, SeqCst) && !force {
+        return;
+    }
+    unsafe {
+        uipi_send(server_sender_id as u64);
+    }
+}
+
+/// 服务端写入回复，回复队列满时挂起，直到客户端取走回复
+pub async fn write_reply(server_sender_id: SenderID, new_buffer: &NewBuffer, item: &IPCItem) {
+    new_buffer.res_queue().write_item(item).await;
+    notify_client(server_sender_id, new_buffer, false);
+}
+
+/// 服务端取出一个请求；若有客户端因请求队列满而挂起，通知其重试
+pub fn recv_request(server_sender_id: SenderID, new_buffer: &NewBuffer) -> Option<IPCItem> {
+    let (item, was_full) = new_buffer.req_queue().take_item()?;
+    if was_full {
+        notify_client(server_sender_id, new_buffer, true);
+    }
+    Some(item)
+}
+
+/// 客户端取出一个回复；若服务端因回复队列满而挂起，通知其重试
+pub fn recv_reply(sender_id: &SenderID, new_buffer: &NewBuffer) -> Option<IPCItem> {
+    let (item, was_full) = new_buffer.res_queue().take_item()?;
+    if was_full {
+        notify_server(sender_id, new_buffer, true);
+    }
+    Some(item)
+}
+
+/// 发送请求并等待回复。每个请求带有唯一的编号，回复按编号交给对应的调用，
+/// 因此同一协程可以借助 join/select 等组合子同时发起多个调用。
+/// 内核的异步系统调用通道（63）不回传编号，其回复仍按 cid 投递到调用协程的邮箱
 pub async fn seL4_Call_with_item(sender_id: &SenderID, item: &IPCItem) -> Result<IPCItem, ()> {
+    call_with_lease(sender_id, item, None).await.map(|(reply, _)| reply)
+}
+
+/// 携带负载区段的 seL4_Call_with_item，收到回复后交还 lease 供调用者读取回复数据。
+/// 请求送出后调用被放弃时，lease 交给未完成调用表，等服务端回复或确认取消后才释放
+pub async fn seL4_Call_with_payload(sender_id: &SenderID, item: &IPCItem, lease: PayloadLease) -> Result<(IPCItem, PayloadLease), ()> {
+    let (reply, lease) = call_with_lease(sender_id, item, Some(lease)).await?;
+    Ok((reply, lease.unwrap()))
+}
+
+async fn call_with_lease(sender_id: &SenderID, item: &IPCItem, lease: Option<PayloadLease>) -> Result<(IPCItem, Option<PayloadLease>), ()> {
     if let Some(new_buffer) = unsafe { convert_option_mut_ref::<NewBuffer>(SENDER_MAP[*sender_id as usize]) } {
-        // todo: bugs need to fix
-        let msg_info = item.msg_info;
-        new_buffer.req_items.write_free_item(&item).unwrap();
-        // debug_println!("seL4_Call_with_item: write item: {:?}", msg_info);
-        if new_buffer.recv_req_status.load(SeqCst) == false {
-            new_buffer.recv_req_status.store(true, SeqCst);
-            if *sender_id != 63 {
-                // debug_println!("send uipi");
-                unsafe {
-                    uipi_send(*sender_id as u64);
-                }
-            } else {
-                // todo: submit syscall
-                // debug_println!("seL4_Call_with_item: Submit Syscall!");
-                unsafe {
-                    SUBMIT_SYSCALL_CNT += 1;
-                }
-                wake_syscall_handler();
-            }
+        if *sender_id == 63 {
+            new_buffer.req_queue().write_item(item).await;
+            notify_server(sender_id, new_buffer, false);
+            return Ok((recv_item().await, lease));
         }
-
-        if let Some(res) = yield_now().await {
-            return Ok(res);
+        let id = PENDING_CALLS.register(&item.cid)?;
+        // 先登记等待者：调用在写入请求前被取消时同样会注销编号
+        let mut reply = PENDING_CALLS.wait(id);
+        let mut item = *item;
+        item.set_request_id(id);
+        // 服务端开启优先级继承时按调用者的优先级处理该请求
+        if let Some(prio) = coroutine_current_prio() {
+            item.set_caller_prio(prio);
         }
+        // 请求队列满时挂起，直到服务端取走请求
+        new_buffer.req_queue().write_item(&item).await;
+        notify_server(sender_id, new_buffer, false);
+        let mut guard = CancelGuard {
+            sender_id: *sender_id,
+            new_buffer,
+            req: item,
+            lease,
+            armed: true,
+        };
+        // 按引用等待：调用被放弃时 guard 先于 reply 释放，lease 交给调用表后编号才被注销
+        let reply = (&mut reply).await;
+        guard.armed = false;
+        return Ok((reply, guard.lease.take()));
     }
     Err(())
 }
 
+/// 带超时的 seL4_Call_with_item：时钟到达 get_clock() + ticks 时仍未收到回复则返回 Err(())，
+/// 并通知服务端取消请求，迟到的回复会被丢弃。
+/// 携带负载区段的请求应使用 timeout_at 包装 seL4_Call_with_payload，区段会保留到服务端回复或确认取消。
+/// 内核通道的回复按 cid 投递、无法撤回，不支持超时
+pub async fn seL4_Call_with_item_timeout(sender_id: &SenderID, item: &IPCItem, ticks: u64) -> Result<IPCItem, ()> {
+    if *sender_id == 63 {
+        return Err(());
+    }
+    timeout_at(seL4_Call_with_item(sender_id, item), get_clock() + ticks).await?
+}
+
+/// 请求队列满、未能发出的取消请求数
+pub static UNSENT_CANCEL_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// 已送出的请求在收到回复前被释放（超时或被 select 等组合子丢弃）时，通知服务端放弃该请求。
+/// 释放时不能挂起，请求队列满时不再发送取消（计入 UNSENT_CANCEL_NUM），服务端照常回复，回复到达后被丢弃。
+/// 请求携带的负载区段交给未完成调用表，服务端回复或确认取消后才归还负载区
+struct CancelGuard {
+    sender_id: SenderID,
+    new_buffer: &'static NewBuffer,
+    req: IPCItem,
+    lease: Option<PayloadLease>,
+    armed: bool,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        if let Some(lease) = self.lease.take() {
+            PENDING_CALLS.abandon(self.req.request_id(), lease);
+        }
+        if self.new_buffer.req_queue().write_free_item(&IPCItem::cancel_of(&self.req)).is_ok() {
+            notify_server(&self.sender_id, self.new_buffer, false);
+        } else {
+            UNSENT_CANCEL_NUM.fetch_add(1, SeqCst);
+            debug_println!("request queue full, cancel not sent: {:?}", self.req);
+        }
+    }
+}
+
 pub async fn seL4_Send_with_item(sender_id: &SenderID, item: &IPCItem) -> Result<IPCItem, ()> {
     // let start = get_clock();
     if let Some(new_buffer) = unsafe { convert_option_mut_ref::<NewBuffer>(SENDER_MAP[*sender_id as usize]) } {
-        // todo: bugs need to fix
-        let msg_info = item.msg_info;
-        new_buffer.req_items.write_free_item(&item).unwrap();
-        // debug_println!("seL4_Call_with_item: write item: {:?}", msg_info);
-        if new_buffer.recv_req_status.load(SeqCst) == false {
-            new_buffer.recv_req_status.store(true, SeqCst);
-            if *sender_id != 63 {
-                // debug_println!("send uipi");
-                unsafe {
-                    uipi_send(*sender_id as u64);
-                }
-            } else {
-                // todo: submit syscall
-                // debug_println!("seL4_Call_with_item: Submit Syscall!");
-                wake_syscall_handler();
-            }
-        }
+        new_buffer.req_queue().write_item(item).await;
+        notify_server(sender_id, new_buffer, false);
         // if let Some(res) = yield_now().await {
         //     return Ok(res);
         // }
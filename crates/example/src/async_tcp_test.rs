@@ -4,7 +4,7 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use core::alloc::Layout;
 use core::mem::{forget, size_of};
-use async_runtime::{coroutine_is_empty, coroutine_run_until_blocked, coroutine_run_until_complete, coroutine_spawn_with_prio, runtime_init, NewBuffer};
+use async_runtime::{coroutine_spawn_supervised, coroutine_spawn_with_prio, block_on, run_forever, runtime_init, runtime_set_idle, IdleStrategy, NewBuffer, RestartPolicy, TaskGroup};
 use sel4::{BootInfo, CPtr, IPCBuffer, LocalCPtr};
 use sel4::cap_type::{Endpoint, Notification, TCB};
 use sel4_root_task::{debug_println, debug_print};
@@ -12,7 +12,7 @@ use sel4::{get_clock, r#yield};
 use uintr::{register_receiver, register_sender};
 use crate::async_lib::{recv_reply_coroutine, register_recv_cid, register_sender_buffer, uintr_handler, AsyncArgs, SenderID, UINT_TRIGGER};
 use crate::image_utils::UserImageUtils;
-use crate::net::{listen, nw_recv_req_coroutine, recv, send, sync_listen, TcpBuffer};
+use crate::net::{listen, nw_recv_req_coroutine, recv, send, set_channel, sync_listen, NetChannel, TcpBuffer};
 use crate::object_allocator::GLOBAL_OBJ_ALLOCATOR;
 
 pub fn net_stack_test(boot_info: &BootInfo) -> sel4::Result<!> {
@@ -20,12 +20,10 @@ pub fn net_stack_test(boot_info: &BootInfo) -> sel4::Result<!> {
     let (ntfn, _) = crate::net::init();
     // BootInfo::init_thread_tcb().tcb_suspend()?;
     create_c_s_ipc_channel(ntfn);
-    // coroutine_run_until_complete();
-    while !coroutine_is_empty() {
-        coroutine_run_until_blocked();
-        r#yield();
-    }
-    unreachable!()
+    // 网卡中断和客户端请求经 uintr handler 唤醒协程时 signal 空闲 notification
+    let idle_ntfn = GLOBAL_OBJ_ALLOCATOR.lock().alloc_ntfn().unwrap();
+    runtime_set_idle(IdleStrategy::Notification(idle_ntfn));
+    run_forever()
 }
 
 
@@ -44,7 +42,8 @@ fn create_c_s_ipc_channel(ntfn: LocalCPtr<Notification>) {
     //     forget(new_buffer);
     //     res
     // };
-    let new_buffer_cap = CPtr::from_bits(UserImageUtils.get_user_image_frame_slot(new_buffer_ref.get_ptr()) as u64);
+    let new_buffer_ptr = new_buffer_ref.get_ptr();
+    let new_buffer_cap = CPtr::from_bits(UserImageUtils.get_user_image_frame_slot(new_buffer_ptr) as u64);
     ntfn.register_async_syscall(new_buffer_cap).unwrap();
     let async_args = {
         let ref_args = Arc::new(AsyncArgs::new());
@@ -58,7 +57,7 @@ fn create_c_s_ipc_channel(ntfn: LocalCPtr<Notification>) {
         GLOBAL_OBJ_ALLOCATOR.lock().get_empty_slot(),
     );
 
-    let cid = coroutine_spawn_with_prio(Box::pin(nw_recv_req_coroutine(async_args.get_ptr())), 1);
+    let cid = coroutine_spawn_supervised(|| Box::pin(nw_recv_req_coroutine()), RestartPolicy::Always, 1);
     let badge = register_recv_cid(&cid).unwrap() as u64;
     assert_eq!(badge, 0);
     let cnode = BootInfo::init_thread_cnode();
@@ -88,6 +87,10 @@ fn create_c_s_ipc_channel(ntfn: LocalCPtr<Notification>) {
         panic!("fail to register_sender!")
     }
     let reply_id = res_send_reply_id.unwrap();
+    set_channel(NetChannel {
+        new_buffer: NewBuffer::from_ptr(new_buffer_ptr),
+        server_sender_id: reply_id as SenderID,
+    });
     let _lock = async_args.lock.lock();
     async_args.server_sender_id = Some(reply_id as SenderID);
     async_args.server_ready = true;
@@ -112,7 +115,7 @@ fn tcp_server_thread(arg: usize, ipc_buffer_addr: usize) {
         r#yield();
     }
     // while async_args.child_tcb.is_none() || async_args.req_ntfn.is_none() || async_args.ipc_new_buffer.is_none() {}
-    let cid = coroutine_spawn_with_prio(Box::pin(recv_reply_coroutine(arg, usize::MAX)), 0);
+    let cid = coroutine_spawn_with_prio(Box::pin(recv_reply_coroutine(arg)), 0);
     let badge = register_recv_cid(&cid).unwrap() as u64;
     let tcb = LocalCPtr::<TCB>::from_bits(async_args.child_tcb.unwrap());
     let reply_ntfn = GLOBAL_OBJ_ALLOCATOR.lock().alloc_ntfn().unwrap();
@@ -129,6 +132,8 @@ fn tcp_server_thread(arg: usize, ipc_buffer_addr: usize) {
 
     tcb.tcb_bind_notification(reply_ntfn).unwrap();
     register_receiver(tcb, reply_ntfn, uintr_handler as usize).unwrap();
+    let idle_ntfn = GLOBAL_OBJ_ALLOCATOR.lock().alloc_ntfn().unwrap();
+    runtime_set_idle(IdleStrategy::Notification(idle_ntfn));
     let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
     let res_sender_id = register_sender_buffer(LocalCPtr::from_bits(async_args.req_ntfn.unwrap()), new_buffer);
     if res_sender_id.is_err() {
@@ -150,15 +155,13 @@ fn tcp_server_thread(arg: usize, ipc_buffer_addr: usize) {
     }
     // while !async_args.server_ready {}
 
+    let mut servers = TaskGroup::with_prio(1);
     for _ in 0..32 {
-        coroutine_spawn_with_prio(Box::pin(tcp_server(sender_id)), 1);
+        servers.spawn(Box::pin(tcp_server(sender_id)));
     }
 
-    // coroutine_run_until_complete();
-    while !coroutine_is_empty() {
-        coroutine_run_until_blocked();
-        r#yield();
-    }
+    // 回复分发协程不会结束，只等待各个服务协程
+    let _ = block_on(Box::pin(async move { servers.join_all().await }));
     debug_println!("server test end");
     loop {
 
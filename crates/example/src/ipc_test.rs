@@ -7,19 +7,18 @@ use core::alloc::Layout;
 use core::mem::{self, size_of};
 use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering::SeqCst;
-use async_runtime::{coroutine_get_current, coroutine_is_empty, coroutine_run_until_blocked, coroutine_run_until_complete, coroutine_spawn, coroutine_spawn_with_prio, get_executor_ptr, runtime_init, Executor, IPCItem, NewBuffer};
+use async_runtime::{block_on, coroutine_get_current, coroutine_run_until_complete, coroutine_spawn_with_prio, get_executor_ptr, runtime_init, runtime_set_idle, Executor, IdleStrategy, IPCItem, NewBuffer, TaskGroup};
 use sel4::{IPCBuffer, LocalCPtr, MessageInfo};
 use sel4::cap_type::{Endpoint, TCB};
 use sel4_root_task::debug_println;
 use sel4::get_clock;
 use sel4::r#yield;
-use uintr::{register_receiver, register_sender, uipi_send};
-use crate::async_lib::{recv_reply_coroutine, register_recv_cid, register_sender_buffer, seL4_Call, seL4_Call_with_item, uintr_handler, yield_now, AsyncArgs, SenderID, UINT_TRIGGER};
+use uintr::{register_receiver, register_sender};
+use crate::async_lib::{recv_reply_coroutine, recv_request, register_recv_cid, register_sender_buffer, seL4_Call, seL4_Call_with_item, uintr_handler, write_reply, yield_now, AsyncArgs, SenderID, UINT_TRIGGER};
 use crate::matrix::matrix_test;
 use crate::object_allocator::GLOBAL_OBJ_ALLOCATOR;
 
 static SEND_NUM: usize = 20480;
-static mut MUTE_SEND_NUM: usize = SEND_NUM;
 static COROUTINE_NUM: usize = 8;
 const MATRIX_SIZE: usize = 4;
 
@@ -51,7 +50,7 @@ pub fn async_helper_thread(arg: usize, ipc_buffer_addr: usize) {
     // }
     let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
     debug_println!("[client] exec_ptr: {:#x}", get_executor_ptr());
-    let cid = coroutine_spawn_with_prio(Box::pin(recv_reply_coroutine(arg, SEND_NUM)), 0);
+    let cid = coroutine_spawn_with_prio(Box::pin(recv_reply_coroutine(arg)), 0);
 
     debug_println!("[client] cid: {:?}, exec_ptr: {:#x}", cid, get_executor_ptr());
     let badge = register_recv_cid(&cid).unwrap() as u64;
@@ -71,6 +70,8 @@ pub fn async_helper_thread(arg: usize, ipc_buffer_addr: usize) {
 
     tcb.tcb_bind_notification(reply_ntfn).unwrap();
     register_receiver(tcb, reply_ntfn, uintr_handler as usize).unwrap();
+    let idle_ntfn = GLOBAL_OBJ_ALLOCATOR.lock().alloc_ntfn().unwrap();
+    runtime_set_idle(IdleStrategy::Notification(idle_ntfn));
 
     let res_sender_id = register_sender_buffer(LocalCPtr::from_bits(async_args.req_ntfn.unwrap()), new_buffer);
     if res_sender_id.is_err() {
@@ -90,20 +91,10 @@ pub fn async_helper_thread(arg: usize, ipc_buffer_addr: usize) {
         drop(_lock);
         r#yield();
     }
-    let base = 100;
-    for i in 0..COROUTINE_NUM {
-        coroutine_spawn(Box::pin(client_call_test(sender_id, (base + i) as u64)));
-    }
-    
     debug_println!("test start");
     let start = get_clock();
-    while !coroutine_is_empty() {
-        // let start_inner = get_clock();
-        coroutine_run_until_blocked();
-        // debug_println!("coroutine_run_until_blocked: {}", get_clock() - start_inner);
-        r#yield();
-    }
-    // coroutine_run_until_complete();
+    // 回复分发协程不会结束，等所有调用完成即可
+    block_on(Box::pin(client_test_main(sender_id)));
     let end = get_clock();
     let uintr_trigger_info = format!("client uintr trigger cnt: {}",
         unsafe { UINT_TRIGGER});
@@ -116,18 +107,26 @@ pub fn async_helper_thread(arg: usize, ipc_buffer_addr: usize) {
 }
 
 
-async fn client_call_test(sender_id: SenderID, msg: u64) {
-    unsafe {
-        while MUTE_SEND_NUM > 0 {
-            MUTE_SEND_NUM -= 1;
-            let item = IPCItem::from(coroutine_get_current(), msg as u32);;
-            if let Ok(_reply) = seL4_Call_with_item(&sender_id, &item).await {
+async fn client_test_main(sender_id: SenderID) {
+    let base = 100;
+    let mut group = TaskGroup::new();
+    for i in 0..COROUTINE_NUM {
+        group.spawn(Box::pin(client_call_test(sender_id, (base + i) as u64, SEND_NUM / COROUTINE_NUM)));
+    }
+    let reply_num: usize = group.join_all().await.unwrap().iter().sum();
+    assert_eq!(reply_num, SEND_NUM);
+}
 
-            } else {
-                panic!("client test fail!")
-            }
+async fn client_call_test(sender_id: SenderID, msg: u64, send_num: usize) -> usize {
+    for _ in 0..send_num {
+        let item = IPCItem::from(coroutine_get_current(), msg as u32);
+        if let Ok(_reply) = seL4_Call_with_item(&sender_id, &item).await {
+
+        } else {
+            panic!("client test fail!")
         }
     }
+    send_num
 }
 
 
@@ -137,17 +136,17 @@ async fn recv_req_coroutine(arg: usize) {
     let async_args= AsyncArgs::from_ptr(arg);
     let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
     loop {
-        if let Some(mut item) = new_buffer.req_items.get_first_item() {
+        new_buffer.res_queue().wake_writers();
+        let server_sender_id = async_args.server_sender_id.unwrap();
+        if let Some(mut item) = recv_request(server_sender_id, new_buffer) {
+            if item.is_cancel() {
+                // 回显请求总是立即完成，没有需要放弃的工作
+                continue;
+            }
             // item.msg_info += 1;
             // debug_println!("hello get item");
             let _res = matrix_test::<MATRIX_SIZE>();
-            new_buffer.res_items.write_free_item(&item).unwrap();
-            if new_buffer.recv_reply_status.load(SeqCst) == false {
-                new_buffer.recv_reply_status.store(true, SeqCst);
-                unsafe {
-                    uipi_send(async_args.server_sender_id.unwrap() as u64);
-                }
-            }
+            write_reply(server_sender_id, new_buffer, &item).await;
             unsafe {
                 REQ_NUM += 1;
                 if REQ_NUM == SEND_NUM {
@@ -186,6 +185,8 @@ pub fn async_ipc_test(_bootinfo: &sel4::BootInfo) -> sel4::Result<!>  {
     let recv_tcb = sel4::BootInfo::init_thread_tcb();
     recv_tcb.tcb_bind_notification(unbadged_notification)?;
     register_receiver(recv_tcb, unbadged_notification, uintr_handler as usize)?;
+    let idle_ntfn = obj_allocator.lock().alloc_ntfn()?;
+    runtime_set_idle(IdleStrategy::Notification(idle_ntfn));
 
     let _lock = async_args.lock.lock();
     async_args.req_ntfn = Some(badged_notification.cptr().bits());
@@ -223,11 +224,7 @@ pub fn async_ipc_test(_bootinfo: &sel4::BootInfo) -> sel4::Result<!>  {
     async_args.server_ready = true;
     drop(_lock);
 
-    // coroutine_run_until_complete();
-    while !coroutine_is_empty() {
-        coroutine_run_until_blocked();
-        r#yield();
-    }
+    coroutine_run_until_complete();
     debug_println!("TEST_PASS");
     let uintr_trigger_info = format!("server uintr cnt: {}",
         unsafe { UINT_TRIGGER });
@@ -12,6 +12,7 @@
 #![feature(slice_index_methods)]
 #![feature(build_hasher_simple_hash_one)]
 #![feature(new_uninit)]
+#![feature(inline_const)]
 #![allow(dead_code, unused_imports)]
 extern crate alloc;
 mod heap;
@@ -28,12 +29,14 @@ mod sync_tcp_test;
 mod poll_net_test;
 mod net;
 mod matrix;
+mod multi_core_test;
 mod memory_allocator;
 
 use alloc::alloc::alloc_zeroed;
 use core::alloc::Layout;
 use core::arch::asm;
 
+use async_runtime::coroutine_set_remove_hook;
 use sel4::{IPCBuffer, with_ipc_buffer};
 use sel4_logging::LevelFilter;
 use sel4_root_task::{debug_print, debug_println};
@@ -45,6 +48,7 @@ use crate::object_allocator::GLOBAL_OBJ_ALLOCATOR;
 use crate::sync_tcp_test::net_stack_test;
 // use crate::async_tcp_test::net_stack_test;
 use crate::poll_net_test::smoltcp_poll_test;
+use crate::multi_core_test::multi_core_test;
 const LOG_LEVEL: LevelFilter = LevelFilter::Info;
 
 static LOGGER: Logger = LoggerBuilder::const_default()
@@ -89,10 +93,12 @@ fn main(bootinfo: &sel4::BootInfo) -> sel4::Result<!> {
     recv_tcb.tcb_set_affinity(0);
     image_utils::UserImageUtils.init(bootinfo);
     GLOBAL_OBJ_ALLOCATOR.lock().init(bootinfo);
+    coroutine_set_remove_hook(async_lib::clear_coroutine_state);
     // async_ipc_test(bootinfo)?;
     // net_stack_test(bootinfo)?;
     // smoltcp_poll_test(bootinfo);
     // sync_ipc_test(bootinfo)?;
+    // multi_core_test(bootinfo)?;
     async_syscall_test(bootinfo)?;
     debug_println!("TEST_PASS");
 
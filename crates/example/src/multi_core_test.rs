@@ -0,0 +1,76 @@
+//! 多核执行器测试：每个核上运行一个参与任务窃取的执行器，0 号执行器生成可窃取的矩阵乘法协程，
+//! 其他执行器没有工作时阻塞在各自的 notification 上，有新的共享协程时被唤醒并窃取。
+
+use alloc::boxed::Box;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering::SeqCst;
+use async_runtime::{coroutine_run_until_complete, coroutine_spawn_shared, runtime_enable_multi_core, runtime_init, runtime_set_idle, IdleStrategy};
+use sel4::{get_clock, r#yield, BootInfo, IPCBuffer};
+use sel4_root_task::debug_println;
+use crate::matrix::matrix_test;
+use crate::object_allocator::GLOBAL_OBJ_ALLOCATOR;
+
+const EXECUTOR_NUM: usize = 4;
+const JOB_NUM: usize = 256;
+const MATRIX_SIZE: usize = 16;
+
+/// 已完成初始化的执行器数
+static READY_NUM: AtomicUsize = AtomicUsize::new(0);
+/// 各执行器完成的矩阵乘法数
+static DONE_NUM: [AtomicUsize; EXECUTOR_NUM] = [const { AtomicUsize::new(0) }; EXECUTOR_NUM];
+
+#[thread_local]
+static mut EXECUTOR_ID: usize = 0;
+
+pub fn multi_core_test(_boot_info: &BootInfo) -> sel4::Result<()> {
+    for id in 1..EXECUTOR_NUM {
+        // 第 id 号执行器绑定在第 id 个核上
+        GLOBAL_OBJ_ALLOCATOR.lock().create_thread(executor_thread, id, 255, id as u64, true)?;
+    }
+    let start = get_clock();
+    run_executor(0);
+    debug_println!("multi core test: {} jobs in {} cycles", JOB_NUM, get_clock() - start);
+    for (id, done) in DONE_NUM.iter().enumerate() {
+        debug_println!("  executor {}: {} jobs", id, done.load(SeqCst));
+    }
+    assert_eq!(DONE_NUM.iter().map(|done| done.load(SeqCst)).sum::<usize>(), JOB_NUM);
+    Ok(())
+}
+
+fn executor_thread(id: usize, ipc_buffer_addr: usize) {
+    let ipc_buffer = ipc_buffer_addr as *mut sel4::sys::seL4_IPCBuffer;
+    let ipcbuf = unsafe {
+        IPCBuffer::from_ptr(ipc_buffer)
+    };
+    sel4::set_ipc_buffer(ipcbuf);
+    run_executor(id);
+    loop {
+
+    }
+}
+
+/// 初始化 id 号执行器并运行到所有共享协程结束
+fn run_executor(id: usize) {
+    unsafe {
+        EXECUTOR_ID = id;
+    }
+    runtime_init();
+    let ntfn = GLOBAL_OBJ_ALLOCATOR.lock().alloc_ntfn().unwrap();
+    runtime_set_idle(IdleStrategy::Notification(ntfn));
+    runtime_enable_multi_core(id);
+    if id == 0 {
+        for _ in 0..JOB_NUM {
+            coroutine_spawn_shared(Box::pin(matrix_job()), 1);
+        }
+    }
+    READY_NUM.fetch_add(1, SeqCst);
+    while READY_NUM.load(SeqCst) < EXECUTOR_NUM {
+        r#yield();
+    }
+    coroutine_run_until_complete();
+}
+
+async fn matrix_job() {
+    let _ = matrix_test::<MATRIX_SIZE>();
+    DONE_NUM[unsafe { EXECUTOR_ID }].fetch_add(1, SeqCst);
+}
@@ -53,7 +53,7 @@ impl ListenTableEntry {
 impl Drop for ListenTableEntry {
     fn drop(&mut self) {
         for &handle in &self.syn_queue {
-            SOCKET_SET.lock().remove(handle);
+            SOCKET_SET.blocking_lock().remove(handle);
         }
     }
 }
@@ -169,14 +169,14 @@ impl ListenTable {
 
 #[inline]
 fn is_connected(handle: SocketHandle) -> bool {
-    let bindings = SOCKET_SET.lock();
+    let bindings = SOCKET_SET.blocking_lock();
     let sock: &Socket = bindings.get(handle);
     sock.state() == State::Established
 }
 
 #[inline]
 fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
-    let bindings = SOCKET_SET.lock();
+    let bindings = SOCKET_SET.blocking_lock();
     let sock: &Socket = bindings.get(handle);
     (sock.local_endpoint().unwrap(), sock.remote_endpoint().unwrap())
 }
@@ -1,6 +1,5 @@
 use smoltcp::iface::SocketHandle;
-use async_runtime::{CoroutineId, IPCItem};
-use crate::net::tcp_buffer::TcpBuffer;
+use async_runtime::{CoroutineId, IPCItem, PayloadList};
 
 pub struct MessageBuilder;
 pub struct MessageDecoder;
@@ -18,6 +17,10 @@ pub enum MessageType {
 }
 
 const INVALID_TYPE: u32 = MessageType::RecvReply as u32 + 1;
+/// 长度在 extend_msg 中占两个字，负载区段可以超过 u16::MAX 字节
+const LEN_WORD: usize = 1;
+/// 负载区段描述符在 extend_msg 中的起始位置
+const PAYLOAD_WORD: usize = LEN_WORD + 2;
 
 impl MessageBuilder {
     #[inline]
@@ -30,57 +33,45 @@ impl MessageBuilder {
     }
 
     #[inline]
-    pub fn listen_reply(cid: CoroutineId, handler: SocketHandle) -> IPCItem {
-        let mut item = IPCItem::default();
-        item.cid = cid;
-        item.msg_info = MessageType::ListenReply as u32;
+    pub fn listen_reply(req: &IPCItem, handler: SocketHandle) -> IPCItem {
+        let mut item = IPCItem::reply_to(req, MessageType::ListenReply as u32);
         item.extend_msg[0] = unsafe { core::mem::transmute::<SocketHandle, usize>(handler) as u16 };
         item
     }
 
     #[inline]
-    pub fn send(cid: CoroutineId, handler: SocketHandle, buffer: &TcpBuffer, len: usize) -> IPCItem {
+    pub fn send(cid: CoroutineId, handler: SocketHandle, payload: &PayloadList) -> IPCItem {
         let mut item = IPCItem::default();
         item.cid = cid;
         item.msg_info = MessageType::Send as u32;
         item.extend_msg[0] = unsafe { core::mem::transmute::<SocketHandle, usize>(handler) as u16 };
-        item.extend_msg[1] = len as u16;
-        let ptr = unsafe {
-            &mut *(item.extend_msg.as_ptr().add(4) as usize as *mut usize)
-        };
-        *ptr = buffer as *const TcpBuffer as usize;
+        item.set_word32(LEN_WORD, payload.len() as u32);
+        item.set_payload(PAYLOAD_WORD, payload).unwrap();
         item
     }
 
     #[inline]
-    pub fn send_reply(cid: CoroutineId, send_size: usize) -> IPCItem {
-        let mut item = IPCItem::default();
-        item.cid = cid;
-        item.msg_info = MessageType::SendReply as u32;
-        item.extend_msg[1] = send_size as u16;
+    pub fn send_reply(req: &IPCItem, send_size: usize) -> IPCItem {
+        let mut item = IPCItem::reply_to(req, MessageType::SendReply as u32);
+        item.set_word32(LEN_WORD, send_size as u32);
         item
     }
 
     #[inline]
-    pub fn recv(cid: CoroutineId, handler: SocketHandle, buffer: &mut TcpBuffer, len: usize) -> IPCItem {
+    pub fn recv(cid: CoroutineId, handler: SocketHandle, payload: &PayloadList) -> IPCItem {
         let mut item = IPCItem::default();
         item.cid = cid;
         item.msg_info = MessageType::Recv as u32;
         item.extend_msg[0] = unsafe { core::mem::transmute::<SocketHandle, usize>(handler) as u16 };
-        item.extend_msg[1] = len as u16;
-        let ptr = unsafe {
-            &mut *(item.extend_msg.as_ptr().add(4) as usize as *mut usize)
-        };
-        *ptr = buffer as *mut TcpBuffer as usize;
+        item.set_word32(LEN_WORD, payload.len() as u32);
+        item.set_payload(PAYLOAD_WORD, payload).unwrap();
         item
     }
 
     #[inline]
-    pub fn recv_reply(cid: CoroutineId, read_size: usize) -> IPCItem {
-        let mut item = IPCItem::default();
-        item.cid = cid;
-        item.msg_info = MessageType::RecvReply as u32;
-        item.extend_msg[1] = read_size as u16;
+    pub fn recv_reply(req: &IPCItem, read_size: usize) -> IPCItem {
+        let mut item = IPCItem::reply_to(req, MessageType::RecvReply as u32);
+        item.set_word32(LEN_WORD, read_size as u32);
         item
     }
 }
@@ -96,12 +87,13 @@ impl MessageDecoder {
         item.extend_msg[0] as usize
     }
 
+    /// 消息类型，标签不属于任何类型（例如对端发来的错误请求）时返回 Err
     #[inline]
-    pub fn get_type(item: &IPCItem) -> MessageType {
-        unsafe {
-            assert!(item.msg_info < INVALID_TYPE);
-            core::mem::transmute::<u8, MessageType>(item.msg_info as u8)
+    pub fn get_type(item: &IPCItem) -> Result<MessageType, ()> {
+        if item.label() >= INVALID_TYPE {
+            return Err(());
         }
+        Ok(unsafe { core::mem::transmute::<u8, MessageType>(item.label() as u8) })
     }
 
     #[inline]
@@ -113,14 +105,12 @@ impl MessageDecoder {
 
     #[inline]
     pub fn get_len(item: &IPCItem) -> usize {
-        item.extend_msg[1] as usize
+        item.get_word32(LEN_WORD) as usize
     }
 
+    /// 请求携带的负载区段，区段是否越界由接收方对照负载区检查
     #[inline]
-    pub fn get_buffer(item: &IPCItem) -> &'static mut TcpBuffer {
-        unsafe {
-            let ptr = *(item.extend_msg.as_ptr().add(4) as usize as *mut usize);
-            &mut *(ptr as *mut TcpBuffer)
-        }
+    pub fn get_payload(item: &IPCItem) -> Result<PayloadList, ()> {
+        item.get_payload(PAYLOAD_WORD)
     }
 }
\ No newline at end of file
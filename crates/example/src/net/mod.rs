@@ -5,23 +5,23 @@ mod listen_table;
 mod sync_tcp;
 
 use alloc::boxed::Box;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
 use alloc::vec;
 use smoltcp::wire::IpEndpoint;
-use core::cmp::min;
 use core::sync::atomic::Ordering::SeqCst;
 use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{Socket, SocketBuffer};
 use smoltcp::time::Instant;
-use spin::{Lazy, Mutex};
-use async_runtime::{coroutine_get_current, coroutine_spawn_with_prio, coroutine_wake, get_ready_num, runtime_init, CoroutineId, IPCItem};
+use spin::{Lazy, Mutex, Once};
+use async_runtime::sync::{Mutex as AsyncMutex, Notify};
+use async_runtime::{consume_budget, coroutine_get_current, coroutine_inherit_prio, coroutine_set_prio_inherit, coroutine_spawn_local_with_prio, coroutine_spawn_with_prio, coroutine_wake, get_ready_num, runtime_init, sleep, CoroutineId, IPCItem, NewBuffer, PayloadArena, PayloadList, PrioBoost};
 use sel4::cap_type::{Endpoint, IRQHandler, Notification};
 use sel4::LocalCPtr;
 
 use sel4_root_task::debug_println;
-use uintr::{register_receiver, uipi_send};
-use crate::async_lib::{register_recv_cid, uintr_handler, wake_with_value, yield_now, AsyncArgs, SenderID, possible_switch};
+use uintr::register_receiver;
+use crate::async_lib::{recv_request, register_recv_cid, uintr_handler, wake_with_value, write_reply, yield_now, recv_item, SenderID};
 use crate::device::{init_net_interrupt_handler, interrupt_handler, INTERFACE, NET_DEVICE};
 
 use sel4::get_clock;
@@ -41,18 +41,45 @@ static mut NET_STACK_MAP: BTreeMap<SocketHandle, SenderID> = BTreeMap::new();
 #[thread_local]
 static mut NET_STACK_MAP2: BTreeMap<SocketHandle, LocalCPtr<Endpoint>> = BTreeMap::new();
 
-pub static SOCKET_SET: Lazy<Arc<Mutex<SocketSet>>> =
-    Lazy::new(|| Arc::new(Mutex::new(SocketSet::new(vec![]))));
+/// 协程中使用 lock().await，同步代码使用 try_lock 或 blocking_lock
+pub static SOCKET_SET: Lazy<Arc<AsyncMutex<SocketSet>>> =
+    Lazy::new(|| Arc::new(AsyncMutex::new(SocketSet::new(vec![]))));
 
 
-pub static SOCKET_2_CID: Lazy<Arc<Mutex<BTreeMap<SocketHandle, CoroutineId>>>> =
-    Lazy::new(|| Arc::new(Mutex::new(BTreeMap::new())));
+pub static SOCKET_2_CID: Lazy<Arc<AsyncMutex<BTreeMap<SocketHandle, CoroutineId>>>> =
+    Lazy::new(|| Arc::new(AsyncMutex::new(BTreeMap::new())));
 
 pub static ADDR_2_CID: Lazy<Arc<Mutex<BTreeMap<IpEndpoint, CoroutineId>>>> =
     Lazy::new(|| Arc::new(Mutex::new(BTreeMap::new())));
 
+/// 转交给 socket 处理协程、尚未回复的请求，以 (cid, 请求编号) 为键。
+/// 客户端取消请求时从表中移除，处理协程发现后放弃该请求
+static DEFERRED_REQS: Lazy<Mutex<BTreeSet<(CoroutineId, u16)>>> =
+    Lazy::new(|| Mutex::new(BTreeSet::new()));
+
+/// 网络服务协程共用的 IPC 通道，按值传给各个协程
+#[derive(Clone, Copy)]
+pub struct NetChannel {
+    pub new_buffer: &'static NewBuffer,
+    pub server_sender_id: SenderID,
+}
+
+/// 回复通道注册完成后由驱动程序设置
+static NET_CHANNEL: Once<NetChannel> = Once::new();
+
+/// 通知等待 NET_CHANNEL 的请求处理协程
+static CHANNEL_READY: Notify = Notify::new();
+
+/// 设置网络服务的 IPC 通道，只有第一次设置生效
+pub fn set_channel(channel: NetChannel) {
+    NET_CHANNEL.call_once(|| channel);
+    CHANNEL_READY.notify_one();
+}
+
 pub fn init() -> (LocalCPtr<Notification>, LocalCPtr<IRQHandler>){
     runtime_init();
+    // 请求携带调用者的优先级，处理协程据此继承
+    coroutine_set_prio_inherit(true);
     let (net_handler, net_ntfn) = init_net_interrupt_handler();
     let tcb = sel4::BootInfo::init_thread_tcb();
     tcb.tcb_bind_notification(net_ntfn).unwrap();
@@ -62,7 +89,8 @@ pub fn init() -> (LocalCPtr<Notification>, LocalCPtr<IRQHandler>){
     unsafe {
         NET_DEVICE_POLLER_CID = cid;
     }
-    // let _ = coroutine_spawn_with_prio(Box::pin(poll_timer(get_clock())), 2);
+    // 没有收发请求时也定期推进协议栈
+    coroutine_spawn_with_prio(Box::pin(poll_timer()), 2);
     // debug_println!("init cid: {:?}", cid);
     // let badge = register_recv_cid(&cid).unwrap() as u64;
     // assert_eq!(badge, 0);
@@ -78,69 +106,93 @@ fn wake_net_device_poller() {
 #[thread_local]
 static mut NET_DEVICE_POLLER_CID: CoroutineId = CoroutineId::from_val(65535);
 
-pub fn iface_poll(urgent: bool) -> bool {
-    // let start = get_clock();
+/// 累计一次轮询请求，urgent 或累计到阈值时返回 true
+fn poll_due(urgent: bool) -> bool {
     static THRESHOLD: usize = 10;
     static mut POLL_CNT: usize = 0;
     unsafe {
         POLL_CNT += 1;
         if urgent || POLL_CNT >= THRESHOLD {
-            // let start = get_clock();
-            let ans = INTERFACE.lock().poll(
-                Instant::ZERO,
-                &mut *NET_DEVICE.as_mut_ptr(),
-                &mut SOCKET_SET.lock(),
-            );
-            NET_POLL_CNT += 1;
-            // NET_POLL_COST += get_clock() - start;
-            // debug_println!("{} {}", NET_POLL_COST, NET_POLL_CNT);
             POLL_CNT = 0;
-            return ans;
+            return true;
         }
         return false;
     }
-    // unsafe {
-    //     NET_POLL_CNT += 1;
-    //     NET_POLL_COST += get_clock() - start;
-    //     debug_println!("{}", NET_POLL_COST);
-    // }
+}
+
+fn poll_sockets(sockets: &mut SocketSet) -> bool {
+    // let start = get_clock();
+    unsafe {
+        NET_POLL_CNT += 1;
+        INTERFACE.lock().poll(
+            Instant::ZERO,
+            &mut *NET_DEVICE.as_mut_ptr(),
+            sockets,
+        )
+    }
+}
+
+/// 协程中轮询网卡。SOCKET_SET 正被其他协程使用时挂起等待，网卡中断后的轮询不会被跳过
+pub async fn iface_poll(urgent: bool) -> bool {
+    if !poll_due(urgent) {
+        return false;
+    }
+    poll_sockets(&mut *SOCKET_SET.lock().await)
+}
+
+/// 同步代码中轮询网卡，SOCKET_SET 被占用时自旋等待
+pub fn iface_poll_blocking(urgent: bool) -> bool {
+    if !poll_due(urgent) {
+        return false;
+    }
+    poll_sockets(&mut *SOCKET_SET.blocking_lock())
 }
 
 static mut POLL_TIMER_CNT:usize = 0;
 
-async fn poll_timer(mut timeout: u64) {
+/// 出现等待网络的请求或待发送的数据时通知 poll_timer 重新开始定时轮询
+static POLL_TIMER_ARM: Notify = Notify::new();
+
+/// 是否有等待网络的请求或尚未发出的数据，需要定时推进协议栈
+async fn stack_busy() -> bool {
+    if !DEFERRED_REQS.lock().is_empty() {
+        return true;
+    }
+    SOCKET_SET.lock().await.iter().any(|(_, socket)| match socket {
+        smoltcp::socket::Socket::Tcp(socket) => socket.send_queue() > 0,
+        #[allow(unreachable_patterns)]
+        _ => false,
+    })
+}
+
+/// 每隔 TIME_INTERVAL 个时钟周期睡醒一次，累计到阈值后轮询一次网卡。
+/// 协议栈空闲时不设定时器，执行器可以阻塞在空闲 notification 上，直到新的请求或网卡中断到达
+async fn poll_timer() {
     static TIME_INTERVAL: u64 = 10000;
-    let cid = coroutine_get_current();
     loop {
         // debug_println!("prio 2 task num: {}", get_ready_num());
-        let cur = get_clock();
-        if cur > timeout {
-            // debug_println!("timer timeout");
-            iface_poll(false);
-            timeout = cur + TIME_INTERVAL;
+        if !stack_busy().await {
+            POLL_TIMER_ARM.notified().await;
+            continue;
         }
-        coroutine_wake(&cid);
-        yield_now().await;
+        sleep(TIME_INTERVAL).await;
+        // debug_println!("timer timeout");
+        iface_poll(false).await;
     }
 }
 
 static mut NET_POLL_CNT: usize = 0;
-static mut NET_POLL_COST: u64 = 0;
 async fn net_poll(handler: LocalCPtr<IRQHandler>) {
     // debug_println!("net poll cid: {:?}", coroutine_get_current());
     loop {
         // debug_println!("hello net poll");
         // let start = get_clock();
         // while iface_poll(true) {};
-        iface_poll(true);
+        iface_poll(true).await;
         interrupt_handler();
         handler.irq_handler_ack();
         // debug_println!("poll end");
-        unsafe {
-            // NET_POLL_COST += get_clock() - start;
-            // NET_POLL_CNT += 1;
-            // debug_println!("iface_poll cost: {}", NET_POLL_COST);
-        }
+        // net_poll 的 poll 次数与耗时见 coroutine_stats / coroutine_dump
 
         // for (handler, socket) in SOCKET_SET.lock().iter() {
         //     debug_println!("get socket, handle: {}, socket: {:?}", handler, socket);
@@ -150,126 +202,238 @@ async fn net_poll(handler: LocalCPtr<IRQHandler>) {
 }
 
 
-pub async fn nw_recv_req_coroutine(arg: usize) {
+/// 网络服务端的请求处理协程，回复通道尚未注册时挂起等待 set_channel。
+/// 回复队列满时挂起等待客户端取走回复，期间不再取新的请求
+pub async fn nw_recv_req_coroutine() -> Result<(), ()> {
     debug_println!("hello recv_req_coroutine");
     static mut REQ_NUM: usize = 0;
-    let async_args= AsyncArgs::from_ptr(arg);
-    let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
-    let mut cnt = 0;
+    let channel = loop {
+        if let Some(channel) = NET_CHANNEL.get() {
+            break *channel;
+        }
+        CHANNEL_READY.notified().await;
+    };
+    let new_buffer = channel.new_buffer;
     loop {
-        if let Some(item) = new_buffer.req_items.get_first_item() {
-            cnt += 1;
-            if let Some(item) = process_req(&item, arg).await {
-                new_buffer.res_items.write_free_item(&item).unwrap();
-                if new_buffer.recv_reply_status.load(SeqCst) == false {
-                    new_buffer.recv_reply_status.store(true, SeqCst);
-                    unsafe { uipi_send(async_args.server_sender_id.unwrap() as u64); }
-                }
-            }
-            if cnt >= 20 {
-                possible_switch().await;
-                cnt = 0;
+        // 客户端取走回复后经 uipi 唤醒本协程，由这里唤醒因回复队列满而挂起的协程
+        new_buffer.res_queue().wake_writers();
+        if let Some(item) = recv_request(channel.server_sender_id, new_buffer) {
+            // 处理请求和写回复期间按调用者的优先级运行
+            let _boost = inherit_caller_prio(&item);
+            if let Some(item) = process_req(&item, channel).await {
+                write_reply(channel.server_sender_id, new_buffer, &item).await;
             }
+            consume_budget().await;
         } else {
             new_buffer.recv_req_status.store(false, SeqCst);
-            cnt = 0;
             yield_now().await;
             // debug_println!("nw recv cnt: {}", cnt);
         }
     }
 }
 
-async fn process_req(item: &IPCItem, arg: usize) -> Option<IPCItem> {
-    let async_args= AsyncArgs::from_ptr(arg);
-    match MessageDecoder::get_type(&item) {
+/// 把当前协程提升到请求携带的调用者优先级，返回值被释放时撤销
+fn inherit_caller_prio(item: &IPCItem) -> Option<PrioBoost> {
+    coroutine_inherit_prio(&coroutine_get_current(), item.caller_prio()?)
+}
+
+async fn process_req(item: &IPCItem, channel: NetChannel) -> Option<IPCItem> {
+    if item.is_cancel() {
+        // 取消总在请求之后到达。仍在等待的 Recv 与 Listen 从表中移除，处理协程发现后不再回复；
+        // Send 与已经回复过的请求照常结束。无论哪种情况都确认取消，
+        // 客户端以回复或确认中先到的一个结束调用、释放负载区段，丢弃后到的一个
+        if DEFERRED_REQS.lock().remove(&(item.cid, item.request_id())) {
+            debug_println!("request cancelled: {:?}", item);
+        }
+        return Some(IPCItem::cancel_of(item));
+    }
+    let msg_type = match MessageDecoder::get_type(&item) {
+        Ok(msg_type) => msg_type,
+        Err(()) => {
+            debug_println!("drop request with unknown type: {:?}", item);
+            return None;
+        }
+    };
+    match msg_type {
         MessageType::NetPollReq => {
             wake_net_device_poller();
             return None;
         }
         MessageType::Listen => {
             let port = MessageDecoder::get_port(&item);
-            coroutine_spawn_with_prio(Box::pin(tcp_accept_coroutine(item.cid, port as u16, async_args)), 2);
+            DEFERRED_REQS.lock().insert((item.cid, item.request_id()));
+            POLL_TIMER_ARM.notify_one();
+            coroutine_spawn_local_with_prio(Box::pin(tcp_accept_coroutine(*item, port as u16, channel)), 2);
         }
         MessageType::Send => {
-            let cid = MessageDecoder::get_cid(&item);
             let handler = MessageDecoder::get_socket_handler(&item);
-            let tcp_buffer = MessageDecoder::get_buffer(&item);
-            let len = MessageDecoder::get_len(&item);
+            let arena = &channel.new_buffer.payload;
+            let payload = match request_payload(&item, arena) {
+                Some(payload) => payload,
+                None => return Some(MessageBuilder::send_reply(item, 0)),
+            };
             // let start = get_clock();
             // iface_poll();
             // debug_println!("empty poll cost: {}", get_clock() - start);
-            let mut bindings = SOCKET_SET.lock();
+            let mut bindings = SOCKET_SET.lock().await;
             let socket: &mut Socket = bindings.get_mut(handler);
             if socket.can_send() {
-                let send_data = &mut tcp_buffer.data[0..len];
-                if let Ok(send_size) = socket.send_slice(&send_data) {
-                    drop(bindings);
-                    let reply = MessageBuilder::send_reply(cid, send_size);
-                    iface_poll(true);
-                    return Some(reply);
-                }
+                let send_size = send_payload(socket, arena, &payload);
+                drop(bindings);
+                let reply = MessageBuilder::send_reply(item, send_size);
+                iface_poll(true).await;
+                // 没能一次发完的数据由定时轮询继续推进
+                POLL_TIMER_ARM.notify_one();
+                return Some(reply);
             } else {
-                // 假设所有数据大小都不大于socket buffer
-                // panic!("fail to send");
+                // 发送缓冲区已满时不等待，回复 0 字节，由客户端决定是否重试
                 drop(bindings);
+                return Some(MessageBuilder::send_reply(item, 0));
             }
         }
         MessageType::Recv => {
-            let cid = MessageDecoder::get_cid(&item);
             let handler: SocketHandle = MessageDecoder::get_socket_handler(&item);
-            let tcp_buffer = MessageDecoder::get_buffer(&item);
-            let len = MessageDecoder::get_len(&item);
-            let min_len = min(tcp_buffer.data.len(), len);
-            let mut bindings = SOCKET_SET.lock();
+            let arena = &channel.new_buffer.payload;
+            let payload = match request_payload(&item, arena) {
+                Some(payload) => payload,
+                None => return Some(MessageBuilder::recv_reply(item, 0)),
+            };
+            let mut bindings = SOCKET_SET.lock().await;
             let socket: &mut Socket = bindings.get_mut(handler);
             if socket.can_recv() {
-                if let Ok(read_size) = socket.recv_slice(&mut tcp_buffer.data[..min_len]) {
-                    drop(bindings);
-                    let reply = MessageBuilder::recv_reply(cid, read_size);
-                    return Some(reply);
-                }
+                let read_size = recv_payload(socket, arena, &payload);
+                drop(bindings);
+                let reply = MessageBuilder::recv_reply(item, read_size);
+                return Some(reply);
             } else {
                 drop(bindings);
                 // coroutine_spawn_with_prio(Box::pin(tcp_recv_coroutine2(cid, handler, tcp_buffer, async_args)), 1);
-                wake_with_value(SOCKET_2_CID.lock().get(&handler).unwrap(), item);
+                let cid = match SOCKET_2_CID.lock().await.get(&handler).copied() {
+                    Some(cid) => cid,
+                    None => {
+                        // 连接还没有交给处理协程（或已关闭），回复 0 字节，由客户端决定是否重试
+                        debug_println!("recv on socket without handler: {:?}", item);
+                        return Some(MessageBuilder::recv_reply(item, 0));
+                    }
+                };
+                DEFERRED_REQS.lock().insert((item.cid, item.request_id()));
+                POLL_TIMER_ARM.notify_one();
+                if !wake_with_value(&cid, item) {
+                    // 处理协程已结束或积压过多，立即回复 0 字节，避免客户端一直等待
+                    debug_println!("fail to defer recv request: {:?}", item);
+                    DEFERRED_REQS.lock().remove(&(item.cid, item.request_id()));
+                    return Some(MessageBuilder::recv_reply(item, 0));
+                }
             }
         }
         _ => {
-            panic!("wrong Request format, item: {:?}", item);
+            debug_println!("drop request that is not a request type: {:?}", item);
         }
     }
     None
 }
 
 
-async fn tcp_recv_coroutine(mut item: Option<IPCItem>, async_args: &mut AsyncArgs) {
-    let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
+/// 取出请求携带的负载区段并检查边界，越界或格式错误时返回 None
+fn request_payload(item: &IPCItem, arena: &PayloadArena) -> Option<PayloadList> {
+    let payload = MessageDecoder::get_payload(item).ok()?;
+    if payload.as_slice().iter().any(|desc| arena.validate(desc).is_err()) {
+        debug_println!("invalid payload in request: {:?}", item);
+        return None;
+    }
+    Some(payload)
+}
+
+/// 依次发送各区段的数据，返回发送的字节数。
+/// 负载区在共享页中，数据直接在区段与 socket 缓冲区之间拷贝，不借出区段的引用
+fn send_payload(socket: &mut Socket, arena: &PayloadArena, payload: &PayloadList) -> usize {
+    let mut send_size = 0;
+    for desc in payload.as_slice() {
+        let len = desc.len as usize;
+        let mut done = 0;
+        while done < len {
+            // socket 缓冲区绕回时一次只能拿到一段连续空间
+            let sent = socket.send(|buf| {
+                let size = buf.len().min(len - done);
+                match arena.read_segment(desc, done, &mut buf[..size]) {
+                    Ok(()) => (size, size),
+                    Err(()) => (0, 0),
+                }
+            });
+            match sent {
+                Ok(size) if size > 0 => done += size,
+                _ => break,
+            }
+        }
+        send_size += done;
+        if done < len {
+            break;
+        }
+    }
+    send_size
+}
+
+/// 依次把数据读到各区段，返回读到的字节数
+fn recv_payload(socket: &mut Socket, arena: &PayloadArena, payload: &PayloadList) -> usize {
+    let mut read_size = 0;
+    for desc in payload.as_slice() {
+        let len = desc.len as usize;
+        let mut done = 0;
+        while done < len {
+            let read = socket.recv(|buf| {
+                let size = buf.len().min(len - done);
+                match arena.write_segment(desc, done, &buf[..size]) {
+                    Ok(()) => (size, size),
+                    Err(()) => (0, 0),
+                }
+            });
+            match read {
+                Ok(size) if size > 0 => done += size,
+                _ => break,
+            }
+        }
+        read_size += done;
+        if done < len {
+            break;
+        }
+    }
+    read_size
+}
+
+async fn tcp_recv_coroutine(mut item: Option<IPCItem>, channel: NetChannel) {
+    let new_buffer = channel.new_buffer;
     loop {
         // debug_println!("tcp_recv_coroutine");
         if item.is_none() {
             // debug_println!("tcp_recv_coroutine yield");
-            item = yield_now().await;
+            item = Some(recv_item().await);
             continue;
         }
         let item_inner = item.take().unwrap();
-        let cid = MessageDecoder::get_cid(&item_inner);
         let handler: SocketHandle = MessageDecoder::get_socket_handler(&item_inner);
-        let tcp_buffer = MessageDecoder::get_buffer(&item_inner);
-        let len = MessageDecoder::get_len(&item_inner);
-        let min_len = min(tcp_buffer.data.len(), len);
+        let payload = match request_payload(&item_inner, &new_buffer.payload) {
+            Some(payload) => payload,
+            None => {
+                let reply = MessageBuilder::recv_reply(&item_inner, 0);
+                write_reply(channel.server_sender_id, new_buffer, &reply).await;
+                continue;
+            }
+        };
+        let key = (item_inner.cid, item_inner.request_id());
+        let _boost = inherit_caller_prio(&item_inner);
         loop {
-            let mut bindings = SOCKET_SET.lock();
+            if !DEFERRED_REQS.lock().contains(&key) {
+                // 客户端已取消，可能已经释放了负载区段
+                break;
+            }
+            let mut bindings = SOCKET_SET.lock().await;
             let socket: &mut Socket = bindings.get_mut(handler);
             if socket.can_recv() {
-                if let Ok(read_size) = socket.recv_slice(&mut tcp_buffer.data[..min_len]) {
-                    drop(bindings);
-                    let reply = MessageBuilder::recv_reply(cid, read_size);
-                    new_buffer.res_items.write_free_item(&reply).unwrap();
-                    if new_buffer.recv_reply_status.load(SeqCst) == false {
-                        new_buffer.recv_reply_status.store(true, SeqCst);
-                        unsafe { uipi_send(async_args.server_sender_id.unwrap() as u64); }
-                    }
-                }
+                let read_size = recv_payload(socket, &new_buffer.payload, &payload);
+                drop(bindings);
+                DEFERRED_REQS.lock().remove(&key);
+                let reply = MessageBuilder::recv_reply(&item_inner, read_size);
+                write_reply(channel.server_sender_id, new_buffer, &reply).await;
                 break;
             } else {
                 drop(bindings);
@@ -281,7 +445,7 @@ async fn tcp_recv_coroutine(mut item: Option<IPCItem>, async_args: &mut AsyncArg
     }
 }
 
-async fn tcp_accept_coroutine(cid: CoroutineId, port: u16, async_args: &mut AsyncArgs) {
+async fn tcp_accept_coroutine(req: IPCItem, port: u16, channel: NetChannel) {
     // debug_println!("start accept_coroutine");
     let tcp_rx_buffer = SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]);
     let tcp_tx_buffer = SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]);
@@ -291,26 +455,29 @@ async fn tcp_accept_coroutine(cid: CoroutineId, port: u16, async_args: &mut Asyn
     tcp_socket.listen(port).unwrap();
     let mut endpoint: IpListenEndpoint = IpListenEndpoint::default();
     endpoint.port = port;
-    let handler = SOCKET_SET.lock().add(tcp_socket);
+    let handler = SOCKET_SET.lock().await.add(tcp_socket);
     debug_println!("start listen");
     unsafe {
         LISTEN_TABLE.listen(endpoint, handler, coroutine_get_current()).unwrap();
         yield_now().await;
     }
-    let new_buffer = async_args.ipc_new_buffer.as_mut().unwrap();
+    let new_buffer = channel.new_buffer;
     if let Ok((handle, (_local_ep, remote_ep))) = unsafe { LISTEN_TABLE.accept(port) } {
-        let reply = MessageBuilder::listen_reply(cid, handle);
-        new_buffer.res_items.write_free_item(&reply).unwrap();
-        if new_buffer.recv_reply_status.load(SeqCst) == false {
-            new_buffer.recv_reply_status.store(true, SeqCst);
-            unsafe { uipi_send(async_args.server_sender_id.unwrap() as u64); }
+        if !DEFERRED_REQS.lock().remove(&(req.cid, req.request_id())) {
+            // 客户端已取消 Listen，没有人会使用这个连接，直接重置
+            debug_println!("listen cancelled, reset connection: {:?}", req);
+            SOCKET_SET.lock().await.get_mut::<Socket>(handle).abort();
+            return;
         }
-        SOCKET_2_CID.lock().insert(handler, coroutine_get_current());        
+        // 先登记处理协程再回复：客户端收到回复后立即发来的 Recv 要能找到它
+        SOCKET_2_CID.lock().await.insert(handler, coroutine_get_current());
+        let reply = MessageBuilder::listen_reply(&req, handle);
+        write_reply(channel.server_sender_id, new_buffer, &reply).await;
         // ADDR_2_CID.lock().insert(remote_ep, coroutine_get_current());
         // debug_println!("accept_addr: {:?}", ip_addr);
     } else {
         panic!("wake failed")
     }
-    tcp_recv_coroutine(None, async_args).await;
+    tcp_recv_coroutine(None, channel).await;
 
 }
\ No newline at end of file
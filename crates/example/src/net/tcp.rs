@@ -1,6 +1,6 @@
 use smoltcp::iface::SocketHandle;
 use async_runtime::coroutine_get_current;
-use crate::async_lib::{seL4_Call_with_item, SenderID};
+use crate::async_lib::{seL4_Call_with_item, seL4_Call_with_payload, sender_buffer, SenderID};
 use crate::net::message::{MessageBuilder, MessageDecoder, MessageType};
 use crate::net::NET_STACK_MAP;
 use crate::net::tcp_buffer::TcpBuffer;
@@ -10,7 +10,9 @@ pub async fn listen(port: usize, nw_sender_id: &SenderID) -> Result<SocketHandle
     let message = MessageBuilder::listen(coroutine_get_current(), port);
     // debug_println!("[listen] message: {:?}", message);
     if let Ok(reply) = seL4_Call_with_item(nw_sender_id, &message).await {
-        assert_eq!(MessageDecoder::get_type(&reply), MessageType::ListenReply);
+        if MessageDecoder::get_type(&reply) != Ok(MessageType::ListenReply) {
+            return Err(());
+        }
         let handler = MessageDecoder::get_socket_handler(&reply);
         unsafe { NET_STACK_MAP.insert(handler, *nw_sender_id); }
         return Ok(handler);
@@ -19,22 +21,29 @@ pub async fn listen(port: usize, nw_sender_id: &SenderID) -> Result<SocketHandle
 }
 
 
+/// 数据先拷贝到通道的负载区，请求中只携带区段描述符。
+/// 区段在收到回复后释放；调用被放弃时等服务端回复或确认取消后再释放
 pub async fn send(handler: SocketHandle, buffer: &TcpBuffer, len: usize) -> Result<usize, ()> {
     let nw_sender_id = unsafe { NET_STACK_MAP.get(&handler).unwrap() };
-    let message = MessageBuilder::send(coroutine_get_current(), handler, buffer, len);
-    if let Ok(reply) = seL4_Call_with_item(nw_sender_id, &message).await {
-        assert_eq!(MessageDecoder::get_type(&reply), MessageType::SendReply);
-        return Ok(MessageDecoder::get_len(&reply));
+    let lease = sender_buffer(nw_sender_id).ok_or(())?.payload.lease(len)?;
+    lease.arena().write(lease.list(), &buffer.data[..len])?;
+    let message = MessageBuilder::send(coroutine_get_current(), handler, lease.list());
+    let (reply, _lease) = seL4_Call_with_payload(nw_sender_id, &message, lease).await?;
+    if MessageDecoder::get_type(&reply) != Ok(MessageType::SendReply) {
+        return Err(());
     }
-    return Err(());
+    Ok(MessageDecoder::get_len(&reply))
 }
 
+/// 服务端把数据写入负载区中分配的区段，收到回复后再拷贝到 buffer
 pub async fn recv(handler: SocketHandle, buffer: &mut TcpBuffer, len: usize) -> Result<usize, ()> {
     let nw_sender_id = unsafe { NET_STACK_MAP.get(&handler).unwrap() };
-    let message = MessageBuilder::recv(coroutine_get_current(), handler, buffer, len);
-    if let Ok(reply) = seL4_Call_with_item(nw_sender_id, &message).await {
-        assert_eq!(MessageDecoder::get_type(&reply), MessageType::RecvReply);
-        return Ok(MessageDecoder::get_len(&reply));
+    let lease = sender_buffer(nw_sender_id).ok_or(())?.payload.lease(len)?;
+    let message = MessageBuilder::recv(coroutine_get_current(), handler, lease.list());
+    let (reply, lease) = seL4_Call_with_payload(nw_sender_id, &message, lease).await?;
+    if MessageDecoder::get_type(&reply) != Ok(MessageType::RecvReply) {
+        return Err(());
     }
-    return Err(());
+    let read_size = MessageDecoder::get_len(&reply).min(len);
+    lease.arena().read(lease.list(), &mut buffer.data[..read_size])
 }
\ No newline at end of file
@@ -16,7 +16,7 @@ use smoltcp::time::Duration;
 use smoltcp::wire::IpListenEndpoint;
 use spin::Mutex;
 // use crate::device::{recv_test, transmit_test};
-use crate::net::{iface_poll, TcpBuffer, LISTEN_TABLE, POLL_EPS, SOCKET_SET};
+use crate::net::{iface_poll_blocking, TcpBuffer, LISTEN_TABLE, POLL_EPS, SOCKET_SET};
 use crate::{
     net::{
         sync_recv, sync_listen, sync_send, MessageType, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN
@@ -65,7 +65,7 @@ static mut COMPLETE_CNT: u8 = 0u8;
 
 #[inline]
 fn net_interrupt_handler(handler: LocalCPtr<IRQHandler>) {
-    iface_poll(true);
+    iface_poll_blocking(true);
     crate::device::interrupt_handler();
     handler.irq_handler_ack();
 }
@@ -127,7 +127,7 @@ fn process_blocked_task(task: &mut RecvBlockedTask) {
     let handler = task.handler;
     let tcp_buffer = &mut task.tcp_buffer;
     let ep = task.ep.clone();
-    let mut bindings = SOCKET_SET.lock();
+    let mut bindings = SOCKET_SET.blocking_lock();
     let socket: &mut Socket = bindings.get_mut(handler);
     if socket.can_recv() {
         if let Ok(read_size) = socket.recv_slice(&mut tcp_buffer.data) {
@@ -165,7 +165,7 @@ fn process_req(ep: LocalCPtr<Endpoint>) {
             tcp_socket.listen(port).unwrap();
             let mut endpoint = IpListenEndpoint::default();
             endpoint.port = port;
-            let handler = SOCKET_SET.lock().add(tcp_socket);
+            let handler = SOCKET_SET.blocking_lock().add(tcp_socket);
             unsafe {
                 LISTEN_TABLE.listen_with_ep(endpoint, handler, ep)
             }
@@ -183,7 +183,7 @@ fn process_req(ep: LocalCPtr<Endpoint>) {
                     }
                 )
             };
-            let mut bindings = SOCKET_SET.lock();
+            let mut bindings = SOCKET_SET.blocking_lock();
             let socket: &mut Socket = bindings.get_mut(handler);
             if socket.can_recv() {
                 let min_len = min(tcp_buffer.data.len(), len);
@@ -221,13 +221,13 @@ fn process_req(ep: LocalCPtr<Endpoint>) {
                     }
                 )
             };
-            let mut bindings = SOCKET_SET.lock();
+            let mut bindings = SOCKET_SET.blocking_lock();
             let socket: &mut Socket = bindings.get_mut(handler);
             if socket.can_send() {
                 let send_data = &mut tcp_buffer.data[0..len];
                 if let Ok(send_size) = socket.send_slice(&send_data) {
                     drop(bindings);
-                    iface_poll(true);
+                    iface_poll_blocking(true);
                     let reply = MessageInfo::new(0, 0, 0, 2);
                     with_ipc_buffer_mut(
                         |ipc_buf| {
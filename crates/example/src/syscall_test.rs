@@ -1,12 +1,12 @@
 use alloc::boxed::Box;
 use alloc::sync::Arc;
-use async_runtime::{coroutine_run_until_complete, coroutine_spawn_with_prio, runtime_init};
+use async_runtime::{block_on, coroutine_spawn_with_prio, runtime_init, runtime_set_idle, IdleStrategy, TaskGroup};
 use sel4::cap_type::_4KPage;
 use spin::Mutex;
 use core::alloc::{Layout};
 use core::mem::size_of;
 use alloc::alloc::alloc_zeroed;
-use async_runtime::{coroutine_run_until_blocked, coroutine_spawn, NewBuffer};
+use async_runtime::{coroutine_spawn, NewBuffer};
 use sel4::{get_clock, CNode, CapRights, LocalCPtr, ObjectBlueprint, ObjectBlueprintArch, VMAttributes, TCB};
 use sel4::{CPtr, Notification};
 use sel4_root_task::debug_println;
@@ -21,7 +21,6 @@ use uintr::register_receiver;
 use super::async_syscall::*;
 //static mut NEW_BUFFER: NewBuffer = NewBuffer::new();
 
-const REPLY_NUM: usize = TEST_REPLY_NUM;
 const OUTPUT_REPLY_NUM: usize = 3;
 const NTFN_REPLY_NUM: usize = 3;
 const MAP_REPLY_NUM: usize = 4;
@@ -50,7 +49,7 @@ pub fn async_syscall_test(bootinfo: &sel4::BootInfo) -> sel4::Result<!> {
         obj_allocator.lock().get_empty_slot(),
     );
     debug_println!("async_syscall_test: spawn recv_reply_coroutine");
-    let cid = coroutine_spawn(Box::pin(recv_reply_coroutine_async_syscall(new_buffer_ptr, REPLY_NUM)));
+    let cid = coroutine_spawn(Box::pin(recv_reply_coroutine_async_syscall(new_buffer_ptr)));
     debug_println!("async_syscall_test: cid: {:?}", cid);
     let badge = register_recv_cid(&cid).unwrap() as u64;
     let cnode = sel4::BootInfo::init_thread_cnode();
@@ -63,6 +62,8 @@ pub fn async_syscall_test(bootinfo: &sel4::BootInfo) -> sel4::Result<!> {
     let recv_tcb = sel4::BootInfo::init_thread_tcb();
     recv_tcb.tcb_bind_notification(unbadged_reply_ntfn)?;
     register_receiver(recv_tcb, unbadged_reply_ntfn, uintr_handler as usize)?;
+    let idle_ntfn = obj_allocator.lock().alloc_ntfn()?;
+    runtime_set_idle(IdleStrategy::Notification(idle_ntfn));
 
     register_async_syscall_buffer(new_buffer_ptr);
     let new_buffer_cap = CPtr::from_bits(UserImageUtils.get_user_image_frame_slot(new_buffer_ptr) as u64);
@@ -309,9 +310,9 @@ fn run_performance_test(is_sync: bool) {
         let time = end - start;
         debug_println!("\nSyncMemoryAllocator: Test Finish!\nTime Sum: {:?}, Average: {:?}", time, time / MAX_PAGE_NUM / EPOCH);
     } else {
-        async_memory_test();
+        let mut group = async_memory_test();
         let start = get_clock() as usize;
-        coroutine_run_until_complete();
+        block_on(Box::pin(async move { group.join_all().await })).unwrap();
         let end = get_clock() as usize;
         let time = end - start;
         debug_println!("\nAsyncMemoryAllocator: Test Finish!\nTime Sum: {:?}, Average: {:?}", time, time / MAX_PAGE_NUM / EPOCH);
@@ -327,9 +328,10 @@ fn run_performance_test_all() {
     let time = end - start;
     debug_println!("\nSyncMemoryAllocator: Test Finish!\nTime Sum: {:?}, Average: {:?}", time, time / MAX_PAGE_NUM / EPOCH);
     debug_println!("syscall invoke count: {:?}", TEST_REPLY_NUM);
-    async_memory_test();
+    let mut group = async_memory_test();
     let start = get_clock() as usize;
-    coroutine_run_until_complete();
+    // 回复分发协程不会结束，只等待测试协程
+    block_on(Box::pin(async move { group.join_all().await })).unwrap();
     let end = get_clock() as usize;
     let time = end - start;
     debug_println!("\nAsyncMemoryAllocator: Test Finish!\nTime Sum: {:?}, Average: {:?}", time, time / MAX_PAGE_NUM / EPOCH);
@@ -363,16 +365,18 @@ fn performance_test_init() {
     page_table.page_table_map(vspace, vaddr, VMAttributes::default());
 }
 
-fn async_memory_test() {
+fn async_memory_test() -> TaskGroup<()> {
     // 测试 
+    let mut group = TaskGroup::with_prio(0);
     let mut vaddr = START_ADDR;
     for i in 0..MAX_PAGE_NUM {
         let frame = unsafe {
             FRAMES
         }[i];
-        coroutine_spawn_with_prio(Box::pin(async_memery_single_test(frame, vaddr)), 0);
+        group.spawn(Box::pin(async_memery_single_test(frame, vaddr)));
         vaddr = vaddr + PAGE_SIZE;
     }
+    group
 }
 
 fn async_address_test(ptr: usize) {